@@ -16,6 +16,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
 fn convert_java_table(input: &Path, license: &str) -> Result<(), Box<dyn std::error::Error>> {
     let output = input.with_extension("rs");
+    let binary_output = input.with_extension("bin");
 
     let input = fs::File::open(input)?;
     let input = io::BufReader::new(GzDecoder::new(io::BufReader::new(input)));
@@ -32,6 +33,13 @@ fn convert_java_table(input: &Path, license: &str) -> Result<(), Box<dyn std::er
 
     gen.write_rust_table(&mut output)?;
     writeln!(output)?;
+    output.flush()?;
+
+    // The same table in the binary format, for `include_bytes!` or loading at runtime with `rstempel::binary`.
+    let binary_output = fs::File::create(binary_output)?;
+    let mut binary_output = io::BufWriter::new(binary_output);
+    gen.write_binary_table(&mut binary_output)?;
+    binary_output.flush()?;
 
     Ok(())
 }
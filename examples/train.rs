@@ -0,0 +1,24 @@
+use rstempel::train::Trainer;
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::io;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = env::args().collect::<Vec<_>>();
+    let dictionary = args
+        .get(1)
+        .expect("Missing dictionary path argument in position 1");
+    let output = args
+        .get(2)
+        .expect("Missing output path argument in position 2");
+    let method = args.get(3).map_or("-ME2", String::as_str);
+
+    let mut trainer = Trainer::new(method);
+    trainer.read_dictionary(io::BufReader::new(fs::File::open(dictionary)?))?;
+    let stemmer = trainer.build();
+
+    let output = io::BufWriter::new(fs::File::create(output)?);
+    stemmer.save(output)?;
+    Ok(())
+}
@@ -0,0 +1,364 @@
+use crate::case;
+use crate::compound::{self, CompoundParts};
+use crate::tokenize::{tokenize, StemmedToken, Token, TokenKind, Tokens};
+use crate::{normalize, Error, Overrides, Stem, StemmerOptions, StopWords};
+use std::borrow::Cow;
+use std::io;
+
+/// Turns text into the terms of a search index, with the same steps as Lucene's `PolishAnalyzer`: the text is split
+/// into tokens with [`tokenize`], which are lowercased, optionally normalized to NFC, checked against a list of
+/// [`StopWords`], and finally the words are stemmed, looking up [`Overrides`] first. Built with
+/// [`Analyzer::builder`].
+///
+/// ```rust
+/// use rstempel::{Analyzer, Stem};
+/// let analyzer = Analyzer::builder(&rstempel::embedded::STEMMER)
+///     .stop_words(["i", "na"].into_iter().collect())
+///     .protect("kraków")
+///     .build();
+/// let terms: Vec<_> = analyzer.analyze("Koty i psy w Krakowie, na Kraków!").collect();
+/// assert_eq!(terms[0].stem, rstempel::embedded::STEMMER.stem("koty"));
+/// assert_eq!((terms[1].text, terms[1].position), ("psy", 2));
+/// assert_eq!(terms.last().unwrap().stem, "kraków");
+/// ```
+#[derive(Debug, Clone)]
+pub struct Analyzer<S> {
+    stemmer: Overrides<S>,
+    stop_words: StopWords,
+    options: StemmerOptions,
+    lowercase: bool,
+    nfc: bool,
+    compounds: Option<CompoundParts>,
+}
+
+impl<S> Analyzer<S> {
+    /// Returns a builder for an analyzer which stems words with `stemmer`. By default, tokens are lowercased but not
+    /// normalized, no words are stopped or overridden, and words are stemmed with the default [`StemmerOptions`].
+    pub fn builder(stemmer: S) -> AnalyzerBuilder<S> {
+        AnalyzerBuilder {
+            analyzer: Analyzer {
+                stemmer: Overrides::new(stemmer),
+                stop_words: StopWords::new(),
+                options: StemmerOptions::new(),
+                lowercase: true,
+                nfc: false,
+                compounds: None,
+            },
+        }
+    }
+
+    pub fn stop_words(&self) -> &StopWords {
+        &self.stop_words
+    }
+
+    pub fn options(&self) -> &StemmerOptions {
+        &self.options
+    }
+
+    pub fn get_ref(&self) -> &S {
+        self.stemmer.get_ref()
+    }
+}
+
+impl<S: Stem> Analyzer<S> {
+    /// Returns the terms of `text`, as tokens whose `stem` is the term. Punctuation is skipped, and positions count
+    /// every other token, so that they have gaps where stop words were removed, like in Lucene.
+    pub fn analyze<'t>(&self, text: &'t str) -> Terms<'_, 't, S> {
+        Terms {
+            analyzer: self,
+            tokens: tokenize(text),
+            position: 0,
+            buf: String::new(),
+        }
+    }
+
+    /// Returns the term of `token`, or `None` if it's a stop word. `buf` is used to stem words.
+    fn term<'t>(&self, token: &Token<'t>, buf: &mut String) -> Option<Cow<'t, str>> {
+        let mut term = Cow::Borrowed(token.text);
+        if self.nfc {
+            term = normalize::nfc(token.text);
+        }
+        if self.lowercase && term.chars().any(char::is_uppercase) {
+            term = Cow::Owned(case::lowercase(&term));
+        }
+        if self.stop_words.contains(&term) {
+            return None;
+        }
+        if token.is_word() && self.stem_word(&term, buf) {
+            return Some(Cow::Owned(buf.as_str().to_owned()));
+        }
+        Some(term)
+    }
+
+    fn stem_word(&self, word: &str, out: &mut String) -> bool {
+        let stem =
+            |part: &str, out: &mut String| self.stemmer.stem_into_with(part, out, &self.options);
+        match self.compounds {
+            // Overrides of a whole compound take precedence over its parts.
+            Some(parts) => self
+                .stemmer
+                .lookup(word, out)
+                .unwrap_or_else(|| compound::stem_parts(word, out, parts, stem)),
+            None => stem(word, out),
+        }
+    }
+}
+
+/// Configures an [`Analyzer`], returned by [`Analyzer::builder`].
+#[derive(Debug, Clone)]
+pub struct AnalyzerBuilder<S> {
+    analyzer: Analyzer<S>,
+}
+
+impl<S> AnalyzerBuilder<S> {
+    /// Whether tokens are lowercased before they're looked up. Stop words and overrides are lowercased to match.
+    pub fn lowercase(mut self, lowercase: bool) -> Self {
+        self.analyzer.lowercase = lowercase;
+        self
+    }
+
+    /// Whether tokens are normalized to NFC before they're looked up, so that stop words and overrides match
+    /// decomposed letters. Stop words are normalized to match.
+    #[cfg(feature = "normalize")]
+    pub fn nfc(mut self, nfc: bool) -> Self {
+        self.analyzer.nfc = nfc;
+        self
+    }
+
+    /// Replaces the stop words, such as with `StopWords::polish()` from the `stop_words` feature.
+    pub fn stop_words(mut self, stop_words: StopWords) -> Self {
+        self.analyzer.stop_words = stop_words;
+        self
+    }
+
+    /// Stems the `parts` of hyphenated compounds on their own, like [`Compounds`](crate::Compounds). Overrides are
+    /// looked up for the whole compound, then for each part.
+    pub fn compounds(mut self, parts: CompoundParts) -> Self {
+        self.analyzer.compounds = Some(parts);
+        self
+    }
+
+    /// Sets the options used to stem words.
+    pub fn options(mut self, options: StemmerOptions) -> Self {
+        self.analyzer.options = options;
+        self
+    }
+
+    /// Stems `word` as `stem`, like [`Overrides::insert`].
+    pub fn override_stem(mut self, word: &str, stem: impl Into<String>) -> Self {
+        self.analyzer.stemmer.insert(word, stem);
+        self
+    }
+
+    /// Leaves `word` unchanged, like [`Overrides::protect`].
+    pub fn protect(mut self, word: &str) -> Self {
+        self.analyzer.stemmer.protect(word);
+        self
+    }
+
+    /// Adds the overrides in a file of tab separated values, see [`Overrides::load_tsv`].
+    pub fn load_overrides_tsv<R: io::BufRead>(mut self, reader: R) -> Result<Self, Error> {
+        self.analyzer.stemmer.load_tsv(reader)?;
+        Ok(self)
+    }
+
+    pub fn build(self) -> Analyzer<S> {
+        let mut analyzer = self.analyzer;
+        if analyzer.nfc {
+            analyzer.stop_words = analyzer
+                .stop_words
+                .map(|word| normalize::nfc(word).into_owned());
+        }
+        if analyzer.lowercase {
+            analyzer.stop_words = analyzer.stop_words.map(case::lowercase);
+            analyzer.stemmer = analyzer.stemmer.ignore_case();
+        }
+        analyzer
+    }
+}
+
+/// An iterator over the terms of a text, see [`Analyzer::analyze`].
+#[derive(Debug, Clone)]
+pub struct Terms<'a, 't, S> {
+    analyzer: &'a Analyzer<S>,
+    tokens: Tokens<'t>,
+    position: usize,
+    buf: String,
+}
+
+impl<'t, S: Stem> Iterator for Terms<'_, 't, S> {
+    type Item = StemmedToken<'t>;
+
+    fn next(&mut self) -> Option<StemmedToken<'t>> {
+        for token in self.tokens.by_ref() {
+            if token.kind == TokenKind::Punctuation {
+                continue;
+            }
+            let position = self.position;
+            self.position += 1;
+            if let Some(term) = self.analyzer.term(&token, &mut self.buf) {
+                return Some(StemmedToken {
+                    text: token.text,
+                    start: token.start,
+                    end: token.end,
+                    position,
+                    kind: token.kind,
+                    stem: term,
+                });
+            }
+        }
+        None
+    }
+}
+
+impl<S: Stem> std::iter::FusedIterator for Terms<'_, '_, S> {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::Suffix;
+
+    fn terms<S: Stem>(analyzer: &Analyzer<S>, text: &str) -> Vec<(usize, String)> {
+        analyzer
+            .analyze(text)
+            .map(|token| (token.position, token.stem.into_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn test_analyze() {
+        let analyzer = Analyzer::builder(Suffix)
+            .stop_words(["I", "się"].into_iter().collect())
+            .override_stem("Ludzie", "człowiek")
+            .protect("KRAKÓW")
+            .build();
+        assert_eq!(
+            terms(
+                &analyzer,
+                "Ludzie, koty i PSY śmieją się w Krakowie/Kraków 2024 WWW.Example.PL."
+            ),
+            [
+                (0, "człowiek".into()),
+                (1, "kotyx".into()),
+                (3, "psy".into()),
+                (4, "śmiejąx".into()),
+                // Words shorter than `min_length` are left unchanged.
+                (6, "w".into()),
+                (7, "krakowiex".into()),
+                (8, "kraków".into()),
+                (9, "2024".into()),
+                (10, "www.example.pl".into()),
+            ]
+        );
+        let token = analyzer.analyze("Kota").next().unwrap();
+        assert_eq!(
+            (token.text, token.range(), token.kind),
+            ("Kota", 0..4, TokenKind::Word)
+        );
+        assert!(analyzer.analyze("i, SIĘ").next().is_none());
+
+        // Unchanged terms are borrowed from the text.
+        let analyzer = Analyzer::builder(Suffix)
+            .options(StemmerOptions::new().min_length(10))
+            .build();
+        let terms: Vec<_> = analyzer.analyze("kota 12").collect();
+        assert!(terms
+            .iter()
+            .all(|token| matches!(token.stem, Cow::Borrowed(_))));
+    }
+
+    #[test]
+    fn test_analyze_case_sensitive() {
+        let analyzer = Analyzer::builder(Suffix)
+            .lowercase(false)
+            .stop_words(["I"].into_iter().collect())
+            .load_overrides_tsv("Ludzie\tCzłowiek\nkraków\n".as_bytes())
+            .unwrap()
+            .build();
+        assert_eq!(
+            terms(&analyzer, "Ludzie ludzie I i Kraków kraków"),
+            [
+                (0, "Człowiek".into()),
+                (1, "ludziex".into()),
+                (3, "i".into()),
+                (4, "Krakówx".into()),
+                (5, "kraków".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_analyze_compounds() {
+        let text = "Polsko-Niemiecki biało-czerwony e-mail";
+        let analyzer = Analyzer::builder(Suffix).build();
+        assert_eq!(
+            terms(&analyzer, text),
+            [
+                (0, "polsko-niemieckix".into()),
+                (1, "biało-czerwonyx".into()),
+                (2, "e-mailx".into()),
+            ]
+        );
+        let analyzer = Analyzer::builder(Suffix)
+            .compounds(CompoundParts::All)
+            .override_stem("Biało-Czerwony", "biało-czerwony")
+            .protect("niemiecki")
+            .build();
+        assert_eq!(
+            terms(&analyzer, text),
+            [
+                (0, "polskox-niemiecki".into()),
+                (1, "biało-czerwony".into()),
+                (2, "e-mailx".into()),
+            ]
+        );
+        let analyzer = Analyzer::builder(Suffix)
+            .compounds(CompoundParts::Last)
+            .build();
+        assert_eq!(
+            terms(&analyzer, text),
+            [
+                (0, "polsko-niemieckix".into()),
+                (1, "biało-czerwonyx".into()),
+                (2, "e-mailx".into()),
+            ]
+        );
+    }
+
+    #[cfg(feature = "normalize")]
+    #[test]
+    fn test_analyze_nfc() {
+        let analyzer = Analyzer::builder(Suffix)
+            .nfc(true)
+            .stop_words(["wie\u{328}c"].into_iter().collect())
+            .build();
+        assert!(analyzer.analyze("WIĘC więc wie\u{328}c").next().is_none());
+    }
+
+    #[cfg(feature = "stop_words")]
+    #[test]
+    fn test_analyze_polish_stop_words() {
+        let analyzer = Analyzer::builder(Suffix)
+            .stop_words(StopWords::polish())
+            .build();
+        assert_eq!(
+            terms(&analyzer, "Np. tzw. koty, WWW i pies"),
+            [(2, "kotyx".into()), (5, "piesx".into())]
+        );
+    }
+
+    #[cfg(feature = "table_2000")]
+    #[test]
+    fn test_analyze_stemmer() {
+        use crate::embedded::STEMMER;
+
+        let analyzer = Analyzer::builder(&STEMMER).build();
+        for (token, word) in analyzer.analyze("Czytałem KSIĄŻKAMI, robiliśmy!").zip([
+            "czytałem",
+            "książkami",
+            "robiliśmy",
+        ]) {
+            assert_eq!(token.stem, STEMMER.stem(word));
+        }
+    }
+}
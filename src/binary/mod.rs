@@ -0,0 +1,525 @@
+//! A compact binary table format which is read in place, e.g. from a memory-mapped file, without any allocation.
+//!
+//! Tables are converted from the Java format with
+//! [`RustGenerator::write_binary_table`](crate::embedded::generate::RustGenerator::write_binary_table). They can also be
+//! embedded with `include_bytes!` and read by a `static` stemmer, see [`Stemmer::from_bytes_unchecked`], which avoids
+//! compiling large tables as Rust code. The `table_2000_bin` feature embeds one as [`STEMMER`].
+//!
+//! ## Format
+//!
+//! Every value is a little-endian `u32`, and the layout mirrors the structures of the `embedded` stemmer:
+//!
+//! - A header of the magic bytes `RSTB`, the format version, and the number of commands, tries, rows and cells.
+//! - Each command, with its kind in the top 8 bits (`0` skip, `1` delete, `2` replace, `3` insert) and its number of
+//!   chars or its char in the low 24 bits.
+//! - The index of the first row of each trie. A trie's rows end where the next trie's begin.
+//! - The index of the first cell of each row, and its number of cells.
+//! - The char of each cell, sorted within each row.
+//! - The row reference of each cell, which is `0` for none or the index of the row within its trie plus one, followed
+//!   by its packed [`CommandSlice`], which is `0` for none.
+
+use crate::embedded::{Command, CommandSlice, FlatTable};
+use crate::explain::{Explanation, Trace};
+use crate::{Error, Stem, StemmerOptions};
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::num::NonZeroU32;
+
+pub(crate) const MAGIC: [u8; 4] = *b"RSTB";
+pub(crate) const VERSION: u32 = 1;
+/// Length of the header, in `u32`s.
+const HEADER_LEN: usize = 6;
+
+#[cfg(feature = "generate")]
+pub(crate) fn encode_command(cmd: Command) -> u32 {
+    match cmd {
+        Command::Skip { chars } => u32::from(chars),
+        Command::Delete { chars } => 1 << 24 | u32::from(chars),
+        Command::Replace { char } => 2 << 24 | u32::from(char),
+        Command::Insert { char } => 3 << 24 | u32::from(char),
+    }
+}
+
+fn decode_command(value: u32) -> Option<Command> {
+    let param = value & 0xFF_FFFF;
+    match value >> 24 {
+        0 => Some(Command::Skip {
+            chars: param.try_into().ok()?,
+        }),
+        1 => Some(Command::Delete {
+            chars: param.try_into().ok()?,
+        }),
+        2 => Some(Command::Replace {
+            char: char::from_u32(param)?,
+        }),
+        3 => Some(Command::Insert {
+            char: char::from_u32(param)?,
+        }),
+        _ => None,
+    }
+}
+
+fn read_u32(bytes: &[u8], index: usize) -> u32 {
+    let start = index * 4;
+    u32::from_le_bytes(bytes[start..start + 4].try_into().unwrap())
+}
+
+/// Like [`read_u32`], for use in a const context.
+const fn const_read_u32(bytes: &[u8], index: usize) -> u32 {
+    let i = index * 4;
+    u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]])
+}
+
+/// A problem with the header of a table, found by [`Stemmer::split`]. Unlike [`Error`], it can be returned in a const
+/// context.
+#[derive(Debug, Clone, Copy)]
+enum HeaderError {
+    Truncated { offset: usize },
+    InvalidMagic,
+    UnsupportedVersion(u32),
+    TrailingData { offset: usize },
+}
+
+impl From<HeaderError> for Error {
+    fn from(err: HeaderError) -> Self {
+        match err {
+            HeaderError::Truncated { offset } => Error::Truncated {
+                offset: offset as u64,
+            },
+            HeaderError::InvalidMagic => Error::InvalidMagic,
+            HeaderError::UnsupportedVersion(version) => Error::UnsupportedVersion(version),
+            HeaderError::TrailingData { offset } => Error::TrailingData {
+                offset: offset as u64,
+            },
+        }
+    }
+}
+
+/// Converted from `stemmer_2000.out` from Stempel by Andrzej Bialecki. http://www.getopt.org/stempel/index.html
+/// Offered under the Apache License 2.0. https://www.apache.org/licenses/LICENSE-2.0
+#[cfg(feature = "table_2000_bin")]
+pub static STEMMER: Stemmer<'static> =
+    Stemmer::from_bytes_unchecked(include_bytes!("../tables/stemmer_2000.out.bin"));
+
+/// A stemmer which reads its table from a byte slice in the binary format.
+#[derive(Clone, Copy)]
+pub struct Stemmer<'a> {
+    commands: &'a [u8],
+    tries: &'a [u8],
+    rows: &'a [u8],
+    chars: &'a [u8],
+    cells: &'a [u8],
+}
+
+impl<'a> Stemmer<'a> {
+    /// Checks that `bytes` hold a well-formed table, so that stemming can't fail, and returns a stemmer which reads it.
+    /// Returns an [`Error`] describing the first problem found otherwise.
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<Self, Error> {
+        let stemmer = Self::split(bytes)?;
+        stemmer.validate()?;
+        Ok(stemmer)
+    }
+
+    /// Returns a stemmer which reads the table in `bytes`, only checking that its header is valid. Stemming may panic
+    /// if the table is malformed. Unlike [`Stemmer::from_bytes`], this can be used in a const context, e.g. to create
+    /// a `static` stemmer from a table embedded with `include_bytes!`.
+    pub const fn from_bytes_unchecked(bytes: &'a [u8]) -> Self {
+        match Self::split(bytes) {
+            Ok(stemmer) => stemmer,
+            Err(_) => panic!("invalid binary stemming table header"),
+        }
+    }
+
+    /// Stems `word` like [`Stem::stem`], returning a trace of the tries consulted and the commands applied.
+    pub fn stem_explain(&self, word: &str) -> Explanation {
+        crate::embedded::explain(self, word)
+    }
+
+    /// Returns the commands which [`Stem::stem`] applies to `word`, or `None` if it leaves the word unchanged without
+    /// looking up any. See [`crate::embedded::apply`].
+    pub fn command_for(&self, word: &str) -> Option<Vec<Command>> {
+        crate::embedded::command_for(self, word)
+    }
+
+    /// Splits `bytes` into the sections listed in its header, if they match its length.
+    const fn split(bytes: &'a [u8]) -> Result<Self, HeaderError> {
+        let truncated = HeaderError::Truncated {
+            offset: bytes.len(),
+        };
+        if bytes.len() < 4 {
+            return Err(truncated);
+        }
+        let magic = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        if u32::from_le_bytes(magic) != u32::from_le_bytes(MAGIC) {
+            return Err(HeaderError::InvalidMagic);
+        }
+        if bytes.len() < HEADER_LEN * 4 {
+            return Err(truncated);
+        }
+        let (header, mut rest) = bytes.split_at(HEADER_LEN * 4);
+        let version = const_read_u32(header, 1);
+        if version != VERSION {
+            return Err(HeaderError::UnsupportedVersion(version));
+        }
+        let num_commands = const_read_u32(header, 2) as usize;
+        let num_tries = const_read_u32(header, 3) as usize;
+        let num_rows = const_read_u32(header, 4) as usize;
+        let num_cells = const_read_u32(header, 5) as usize;
+        // Lengths of each section in u32s.
+        let lens = [
+            Some(num_commands),
+            Some(num_tries),
+            num_rows.checked_mul(2),
+            Some(num_cells),
+            num_cells.checked_mul(2),
+        ];
+        let mut sections: [&[u8]; 5] = [&[]; 5];
+        let mut i = 0;
+        while i < lens.len() {
+            let len = match lens[i] {
+                Some(len) => match len.checked_mul(4) {
+                    Some(len) if len <= rest.len() => len,
+                    _ => return Err(truncated),
+                },
+                None => return Err(truncated),
+            };
+            (sections[i], rest) = rest.split_at(len);
+            i += 1;
+        }
+        if !rest.is_empty() {
+            return Err(HeaderError::TrailingData {
+                offset: bytes.len() - rest.len(),
+            });
+        }
+        let [commands, tries, rows, chars, cells] = sections;
+        Ok(Self {
+            commands,
+            tries,
+            rows,
+            chars,
+            cells,
+        })
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        let num_commands = self.commands.len() / 4;
+        for idx in 0..num_commands {
+            if decode_command(read_u32(self.commands, idx)).is_none() {
+                return Err(Error::InvalidCommand {
+                    offset: ((HEADER_LEN + idx) * 4) as u64,
+                });
+            }
+        }
+        let chars_offset =
+            HEADER_LEN * 4 + self.commands.len() + self.tries.len() + self.rows.len();
+        let num_cells = self.chars.len() / 4;
+        for trie in 0..self.num_tries() {
+            let rows = self.trie_rows(trie);
+            if rows.is_empty() || rows.end > self.rows.len() / 8 {
+                return Err(Error::InvalidRoot {
+                    trie,
+                    root: read_u32(self.tries, trie),
+                });
+            }
+            let trie_len = rows.len();
+            let first_row = rows.start;
+            for row in rows {
+                let cells = self.row_cells(row);
+                let invalid_row = Error::InvalidRow {
+                    trie,
+                    row: row - first_row,
+                };
+                if cells.end > num_cells || cells.start > cells.end {
+                    return Err(invalid_row);
+                }
+                let mut prev = None;
+                for cell in cells {
+                    let value = read_u32(self.chars, cell);
+                    let ch = char::from_u32(value).ok_or(Error::InvalidChar {
+                        offset: (chars_offset + cell * 4) as u64,
+                    })?;
+                    if prev >= Some(ch) {
+                        return Err(invalid_row);
+                    }
+                    prev = Some(ch);
+                    if read_u32(self.cells, cell * 2) as usize > trie_len {
+                        return Err(Error::InvalidRowReference {
+                            trie,
+                            row: row - first_row,
+                            cell: ch,
+                        });
+                    }
+                    if let Some(cs) = NonZeroU32::new(read_u32(self.cells, cell * 2 + 1)) {
+                        let cs = CommandSlice(cs);
+                        if !cs.is_eom()
+                            && (cs.len() == 0 || cs.start_index() + cs.len() > num_commands)
+                        {
+                            return Err(Error::InvalidCommandIndex {
+                                trie,
+                                row: row - first_row,
+                                cell: ch,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn trie_rows(&self, trie: usize) -> std::ops::Range<usize> {
+        let start = read_u32(self.tries, trie) as usize;
+        let end = if trie + 1 < self.num_tries() {
+            read_u32(self.tries, trie + 1) as usize
+        } else {
+            self.rows.len() / 8
+        };
+        start..end
+    }
+
+    fn row_cells(&self, row: usize) -> std::ops::Range<usize> {
+        let start = read_u32(self.rows, row * 2) as usize;
+        start..start.saturating_add(read_u32(self.rows, row * 2 + 1) as usize)
+    }
+
+    /// Returns the index of the cell for `ch` in the row.
+    fn find_cell(&self, row: usize, ch: char) -> Option<usize> {
+        let ch = u32::from(ch);
+        let cells = self.row_cells(row);
+        let (mut lo, mut hi) = (cells.start, cells.end);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            match read_u32(self.chars, mid).cmp(&ch) {
+                Ordering::Less => lo = mid + 1,
+                Ordering::Greater => hi = mid,
+                Ordering::Equal => return Some(mid),
+            }
+        }
+        None
+    }
+}
+
+impl FlatTable for Stemmer<'_> {
+    type Commands = CommandSlice;
+
+    fn num_tries(&self) -> usize {
+        self.tries.len() / 4
+    }
+
+    fn lookup(
+        &self,
+        trie: usize,
+        word: &str,
+        trace: &mut impl Trace<CommandSlice>,
+    ) -> Option<CommandSlice> {
+        let first_row = read_u32(self.tries, trie) as usize;
+        let mut row = first_row;
+        let mut last = None;
+        let mut chars = word.chars().rev().peekable();
+        while let Some(ch) = chars.next() {
+            let cell = match self.find_cell(row, ch) {
+                Some(cell) => cell,
+                None => break,
+            };
+            let cmds = NonZeroU32::new(read_u32(self.cells, cell * 2 + 1));
+            trace.step(row - first_row, ch, cmds.is_some());
+            if let Some(cmds) = cmds {
+                last = Some(CommandSlice(cmds));
+            }
+            if chars.peek().is_none() {
+                break; // Don't check references on last char
+            }
+            match read_u32(self.cells, cell * 2).checked_sub(1) {
+                Some(next_row) => row = first_row + next_row as usize,
+                None => break,
+            }
+        }
+        last
+    }
+
+    fn command(&self, index: usize) -> Command {
+        decode_command(read_u32(self.commands, index)).unwrap()
+    }
+}
+
+impl Stem for Stemmer<'_> {
+    fn stem<'a>(&self, word: &'a str) -> Cow<'a, str> {
+        crate::embedded::stem(self, word)
+    }
+
+    fn stem_into(&self, word: &str, out: &mut String) -> bool {
+        crate::embedded::stem_into(self, word, out, &StemmerOptions::new())
+    }
+
+    fn stem_into_with(&self, word: &str, out: &mut String, options: &StemmerOptions) -> bool {
+        crate::embedded::stem_into(self, word, out, options)
+    }
+}
+
+#[cfg(all(test, feature = "generate"))]
+mod test {
+    use super::*;
+    use crate::embedded::generate::RustGenerator;
+    use crate::test_util::{load_stemmer, open_gz, STEMMER_2000};
+
+    const WORDS: &[&str] = &[
+        "kotami",
+        "książkami",
+        "czytałem",
+        "ludziom",
+        "mieszkanie",
+        "warszawskiego",
+        "prezydenta",
+        "Abadan",
+        "samochodów",
+        "polskiego",
+        "robiliśmy",
+        "oczach",
+        "siostry",
+        "pięknymi",
+        "żółwiami",
+        "kot",
+    ];
+
+    fn convert_table() -> Vec<u8> {
+        let gen = RustGenerator::load_java_table(open_gz(STEMMER_2000)).unwrap();
+        let mut bytes = Vec::new();
+        gen.write_binary_table(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_stem_matches_external() {
+        let bytes = convert_table();
+        let stemmer = Stemmer::from_bytes(&bytes).unwrap();
+        let external = load_stemmer();
+
+        for word in WORDS {
+            assert_eq!(stemmer.stem(word), external.stem(word), "{}", word);
+        }
+    }
+
+    #[cfg(feature = "table_2000")]
+    #[test]
+    fn test_stem_explain_matches_embedded() {
+        let bytes = convert_table();
+        let stemmer = Stemmer::from_bytes(&bytes).unwrap();
+        for word in WORDS {
+            let explanation = stemmer.stem_explain(word);
+            assert_eq!(explanation.stem, stemmer.stem(word));
+            assert_eq!(explanation, crate::embedded::STEMMER.stem_explain(word));
+            assert_eq!(
+                stemmer.command_for(word),
+                crate::embedded::STEMMER.command_for(word)
+            );
+        }
+    }
+
+    #[cfg(feature = "table_2000_bin")]
+    #[test]
+    fn test_embedded_table() {
+        let bytes = include_bytes!("../tables/stemmer_2000.out.bin");
+        assert!(
+            bytes[..] == convert_table(),
+            "regenerate with `examples/generate.rs`"
+        );
+        assert!(Stemmer::from_bytes(bytes).is_ok());
+        let external = load_stemmer();
+        for word in WORDS {
+            assert_eq!(STEMMER.stem(word), external.stem(word), "{}", word);
+        }
+    }
+
+    #[test]
+    fn test_patched_table() {
+        // Patching moves the root of each trie away from the first row.
+        let mut external = load_stemmer();
+        external.add_pair("ludzie", "człowiek");
+        let mut saved = Vec::new();
+        external.save(&mut saved).unwrap();
+        let gen = RustGenerator::load_java_table(saved.as_slice()).unwrap();
+        let mut bytes = Vec::new();
+        gen.write_binary_table(&mut bytes).unwrap();
+        let stemmer = Stemmer::from_bytes(&bytes).unwrap();
+
+        assert_eq!(stemmer.stem("ludzie"), "człowiek");
+        for word in WORDS {
+            assert_eq!(stemmer.stem(word), external.stem(word), "{}", word);
+        }
+    }
+
+    #[test]
+    fn test_rejects_invalid_tables() {
+        let bytes = convert_table();
+        let len = bytes.len() as u64;
+        assert!(matches!(
+            Stemmer::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(Error::Truncated { offset }) if offset == len - 1
+        ));
+        assert!(matches!(
+            Stemmer::from_bytes(&[]),
+            Err(Error::Truncated { offset: 0 })
+        ));
+        assert!(matches!(
+            Stemmer::from_bytes(&bytes[..12]),
+            Err(Error::Truncated { offset: 12 })
+        ));
+        let mut longer = bytes.clone();
+        longer.extend_from_slice(&[0; 4]);
+        assert!(matches!(
+            Stemmer::from_bytes(&longer),
+            Err(Error::TrailingData { offset }) if offset == len
+        ));
+
+        let mut bad_magic = bytes.clone();
+        bad_magic[0] = b'X';
+        assert!(matches!(
+            Stemmer::from_bytes(&bad_magic),
+            Err(Error::InvalidMagic)
+        ));
+        let mut bad_version = bytes.clone();
+        bad_version[4..8].copy_from_slice(&2u32.to_le_bytes());
+        assert!(matches!(
+            Stemmer::from_bytes(&bad_version),
+            Err(Error::UnsupportedVersion(2))
+        ));
+
+        let mut bad_command = bytes.clone();
+        let first_command = HEADER_LEN * 4;
+        bad_command[first_command..first_command + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(
+            Stemmer::from_bytes(&bad_command),
+            Err(Error::InvalidCommand { offset }) if offset == first_command as u64
+        ));
+
+        // Point the first cell's reference past the end of its trie.
+        let num_cells = read_u32(&bytes, 5) as usize;
+        let mut bad_ref = bytes.clone();
+        let first_cell = bytes.len() - num_cells * 8;
+        bad_ref[first_cell..first_cell + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(
+            Stemmer::from_bytes(&bad_ref),
+            Err(Error::InvalidRowReference {
+                trie: 0,
+                row: 0,
+                ..
+            })
+        ));
+
+        // Swap the chars of the first two cells.
+        let first_char = first_cell - num_cells * 4;
+        let mut unsorted = bytes.clone();
+        unsorted[first_char..first_char + 8].rotate_left(4);
+        assert!(matches!(
+            Stemmer::from_bytes(&unsorted),
+            Err(Error::InvalidRow { trie: 0, row: 0 })
+        ));
+
+        let mut bad_char = bytes.clone();
+        bad_char[first_char..first_char + 4].copy_from_slice(&0xD800u32.to_le_bytes());
+        assert!(matches!(
+            Stemmer::from_bytes(&bad_char),
+            Err(Error::InvalidChar { offset }) if offset == first_char as u64
+        ));
+    }
+}
@@ -0,0 +1,215 @@
+use crate::{Stem, StemmerOptions};
+use std::borrow::Cow;
+
+/// The casing pattern of a word, which is re-applied to its stem.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Casing {
+    /// No uppercase chars, including words without cased chars.
+    Lower,
+    /// At least two cased chars, all uppercase.
+    Upper,
+    /// The first cased char is uppercase and the rest are lowercase.
+    Title,
+    Mixed,
+}
+
+impl Casing {
+    pub fn of(word: &str) -> Self {
+        let mut cased = word
+            .chars()
+            .filter(|c| c.is_lowercase() || c.is_uppercase());
+        let first = match cased.next() {
+            Some(first) => first,
+            None => return Casing::Lower,
+        };
+        let (mut upper, mut lower) = (0, 0);
+        for ch in cased {
+            if ch.is_uppercase() {
+                upper += 1;
+            } else {
+                lower += 1;
+            }
+        }
+        match (first.is_uppercase(), upper, lower) {
+            (false, 0, _) => Casing::Lower,
+            (true, 1.., 0) => Casing::Upper,
+            (true, 0, _) => Casing::Title,
+            _ => Casing::Mixed,
+        }
+    }
+
+    /// Applies this casing to a lowercase word.
+    pub fn apply(self, word: &mut String) {
+        match self {
+            Casing::Lower | Casing::Mixed => {}
+            Casing::Upper => *word = word.chars().map(to_upper).collect(),
+            Casing::Title => {
+                if let Some((idx, ch)) = word.char_indices().find(|(_, ch)| ch.is_lowercase()) {
+                    word.replace_range(
+                        idx..idx + ch.len_utf8(),
+                        to_upper(ch).encode_utf8(&mut [0; 4]),
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Lowercases a char, unless it doesn't map to a single char. This covers every Polish letter, while keeping the
+/// word's length for the tables, which index it by char.
+pub(crate) fn to_lower(ch: char) -> char {
+    let mut lower = ch.to_lowercase();
+    match (lower.next(), lower.next()) {
+        (Some(lower), None) => lower,
+        _ => ch,
+    }
+}
+
+/// Lowercases a word char by char, see [`to_lower`].
+pub(crate) fn lowercase(word: &str) -> String {
+    word.chars().map(to_lower).collect()
+}
+
+/// Uppercases a char, unless it doesn't map to a single char, like `ß`.
+pub(crate) fn to_upper(ch: char) -> char {
+    let mut upper = ch.to_uppercase();
+    match (upper.next(), upper.next()) {
+        (Some(upper), None) => upper,
+        _ => ch,
+    }
+}
+
+/// A stemmer which looks up words in lowercase, then gives their stems the casing of the word, so that e.g. `KOTAMI`,
+/// `Kotami` and `kotami` are stemmed alike.
+///
+/// Words in all caps or title case are stemmed this way. Other words, including mixed-case words such as `McDonald`
+/// whose casing can't be carried over to a stem, are stemmed as they are.
+///
+/// ```rust
+/// use rstempel::{IgnoreCase, Stem};
+/// let stemmer = IgnoreCase::new(&rstempel::embedded::STEMMER);
+/// let stem = stemmer.stem("kotami");
+/// assert_eq!(stemmer.stem("KOTAMI"), stem.to_uppercase());
+/// ```
+#[derive(Debug, Clone)]
+pub struct IgnoreCase<S> {
+    stemmer: S,
+}
+
+impl<S> IgnoreCase<S> {
+    pub fn new(stemmer: S) -> Self {
+        Self { stemmer }
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.stemmer
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stemmer
+    }
+}
+
+impl<S: Stem> IgnoreCase<S> {
+    fn stem_cased(
+        &self,
+        word: &str,
+        out: &mut String,
+        stem: impl FnOnce(&S, &str, &mut String) -> bool,
+    ) -> bool {
+        let casing = Casing::of(word);
+        if matches!(casing, Casing::Lower | Casing::Mixed) {
+            return stem(&self.stemmer, word, out);
+        }
+        let lower = lowercase(word);
+        if stem(&self.stemmer, &lower, out) {
+            casing.apply(out);
+            if out != word {
+                return true;
+            }
+        }
+        out.clear();
+        out.push_str(word);
+        false
+    }
+}
+
+impl<S: Stem> Stem for IgnoreCase<S> {
+    fn stem<'a>(&self, word: &'a str) -> Cow<'a, str> {
+        let mut out = String::new();
+        if self.stem_into(word, &mut out) {
+            Cow::Owned(out)
+        } else {
+            Cow::Borrowed(word)
+        }
+    }
+
+    fn stem_into(&self, word: &str, out: &mut String) -> bool {
+        self.stem_cased(word, out, |stemmer, word, out| stemmer.stem_into(word, out))
+    }
+
+    fn stem_into_with(&self, word: &str, out: &mut String, options: &StemmerOptions) -> bool {
+        self.stem_cased(word, out, |stemmer, word, out| {
+            stemmer.stem_into_with(word, out, options)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_casing() {
+        assert_eq!(Casing::of("kota"), Casing::Lower);
+        assert_eq!(Casing::of("2024"), Casing::Lower);
+        assert_eq!(Casing::of("KOTA"), Casing::Upper);
+        assert_eq!(Casing::of("ŻÓŁW"), Casing::Upper);
+        assert_eq!(Casing::of("Kota"), Casing::Title);
+        assert_eq!(Casing::of("Łódź"), Casing::Title);
+        assert_eq!(Casing::of("A"), Casing::Title);
+        assert_eq!(Casing::of("McDonald"), Casing::Mixed);
+        assert_eq!(Casing::of("kOTA"), Casing::Mixed);
+
+        let mut word = String::from("żółw");
+        Casing::Upper.apply(&mut word);
+        assert_eq!(word, "ŻÓŁW");
+        let mut word = String::from("łódź");
+        Casing::Title.apply(&mut word);
+        assert_eq!(word, "Łódź");
+        assert_eq!(to_lower('Ź'), 'ź');
+        assert_eq!(to_upper('ß'), 'ß');
+    }
+
+    #[cfg(feature = "table_2000")]
+    #[test]
+    fn test_ignore_case() {
+        use crate::embedded::STEMMER;
+
+        let stemmer = IgnoreCase::new(&STEMMER);
+        for word in [
+            "kotami",
+            "książkami",
+            "żółwiami",
+            "czytałem",
+            "robiliśmy",
+            "kot",
+        ] {
+            let stem = STEMMER.stem(word);
+            assert_eq!(stemmer.stem(word), stem);
+            assert_eq!(stemmer.stem(&word.to_uppercase()), stem.to_uppercase());
+            let mut title = word.to_owned();
+            Casing::Title.apply(&mut title);
+            let mut title_stem = stem.into_owned();
+            Casing::Title.apply(&mut title_stem);
+            assert_eq!(stemmer.stem(&title), title_stem);
+        }
+        assert_eq!(stemmer.stem("KoTaMi"), STEMMER.stem("KoTaMi"));
+        assert!(matches!(stemmer.stem("KOT"), Cow::Borrowed("KOT")));
+
+        let options = StemmerOptions::new().max_stages(0);
+        let mut out = String::new();
+        assert!(!stemmer.stem_into_with("KOTAMI", &mut out, &options));
+        assert_eq!(out, "KOTAMI");
+    }
+}
@@ -0,0 +1,209 @@
+//! The patch commands which transform a word into its stem, shared by every stemmer.
+//!
+//! In the Java tables a patch is a string of two-char commands applied from the end of the word towards its start:
+//! `-x` skips, `Dx` deletes, `Rc` replaces the current char with `c` and `Ic` inserts `c`. The `x` parameter counts
+//! chars, where `a` is 1, `b` is 2 and so on.
+
+#[cfg(feature = "external")]
+use crate::Error;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Command {
+    Skip { chars: u8 },
+    Delete { chars: u8 },
+    Replace { char: char },
+    Insert { char: char },
+}
+
+impl std::fmt::Display for Command {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Command::Skip { chars } => write!(f, "SKP {}", chars),
+            Command::Delete { chars } => write!(f, "DEL {}", chars),
+            Command::Replace { char } => write!(f, "SET {}", char),
+            Command::Insert { char } => write!(f, "INS {}", char),
+        }
+    }
+}
+
+impl Command {
+    pub fn parse(cmd: char, param: char) -> Option<Self> {
+        match cmd {
+            '-' => {
+                let chars = u32::from(param)
+                    .checked_sub(u32::from('a'))?
+                    .try_into()
+                    .ok()?;
+                Some(Self::Skip { chars })
+            }
+            'D' => {
+                let chars = u32::from(param)
+                    .checked_sub(u32::from('a'))?
+                    .try_into()
+                    .ok()?;
+                Some(Self::Delete { chars })
+            }
+            'R' => Some(Self::Replace { char: param }),
+            'I' => Some(Self::Insert { char: param }),
+            _ => None,
+        }
+    }
+
+    /// Parses every command of a patch string.
+    #[cfg(feature = "external")]
+    pub(crate) fn parse_patch(patch: &str) -> Result<Vec<Self>, InvalidPatch> {
+        let mut chars = patch.chars();
+        let mut commands = Vec::with_capacity(patch.len() / 2);
+        while let Some(cmd) = chars.next() {
+            let param = chars.next();
+            let parsed = match (cmd, param) {
+                ('-' | 'D' | 'R' | 'I', Some(param)) => Command::parse(cmd, param),
+                ('-' | 'D' | 'R' | 'I', None) => None,
+                (opcode, _) => return Err(InvalidPatch::UnknownOpcode(opcode)),
+            };
+            commands.push(parsed.ok_or(InvalidPatch::InvalidParameter(param))?);
+        }
+        Ok(commands)
+    }
+
+    /// Appends the command to a patch string, the inverse of [`Command::parse`].
+    #[cfg(feature = "external")]
+    pub(crate) fn push_to(self, patch: &mut String) {
+        let (opcode, param) = match self {
+            Command::Skip { chars } => ('-', run_param(chars)),
+            Command::Delete { chars } => ('D', run_param(chars)),
+            Command::Replace { char } => ('R', char),
+            Command::Insert { char } => ('I', char),
+        };
+        patch.push(opcode);
+        patch.push(param);
+    }
+
+    pub(crate) fn cannot_follow(&self, prev: Command) -> bool {
+        matches!(
+            (&self, prev),
+            (Command::Skip { .. }, Command::Skip { .. })
+                | (Command::Delete { .. }, Command::Delete { .. })
+        )
+    }
+
+    pub(crate) fn is_skip(&self) -> bool {
+        matches!(*self, Command::Skip { .. })
+    }
+    #[allow(dead_code)]
+    fn is_delete(&self) -> bool {
+        matches!(*self, Command::Delete { .. })
+    }
+    #[allow(dead_code)]
+    fn is_replace(&self) -> bool {
+        matches!(*self, Command::Replace { .. })
+    }
+    #[allow(dead_code)]
+    fn is_insert(&self) -> bool {
+        matches!(*self, Command::Insert { .. })
+    }
+
+    /// Returns the number of chars of the key which the command skips, deletes or replaces.
+    pub(crate) fn length_pp(&self) -> usize {
+        match *self {
+            Command::Skip { chars } | Command::Delete { chars } => chars as usize + 1,
+            Command::Replace { .. } => 1,
+            Command::Insert { .. } => 0,
+        }
+    }
+}
+
+/// Returns the parameter of a skip or delete command which covers `chars + 1` chars.
+#[cfg(feature = "external")]
+fn run_param(chars: u8) -> char {
+    char::from_u32(u32::from('a') + u32::from(chars)).expect("run parameters are below U+0160")
+}
+
+/// Returns the number of chars of the key which `commands` skip, delete or replace.
+#[cfg(feature = "external")]
+pub(crate) fn length_pp(commands: &[Command]) -> usize {
+    commands.iter().map(Command::length_pp).sum()
+}
+
+/// Why a patch string couldn't be parsed, before it's known where in a table it's stored.
+#[cfg(feature = "external")]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum InvalidPatch {
+    UnknownOpcode(char),
+    /// A parameter which is out of range for its opcode, or `None` if it's missing.
+    InvalidParameter(Option<char>),
+}
+
+#[cfg(feature = "external")]
+impl InvalidPatch {
+    /// Returns the error for `command`, the command string with index `index` in trie `trie`, read at byte `offset`,
+    /// which is referred to by `cell`.
+    pub(crate) fn at(
+        self,
+        trie: usize,
+        index: usize,
+        offset: u64,
+        cell: Option<(usize, char)>,
+        command: String,
+    ) -> Error {
+        match self {
+            InvalidPatch::UnknownOpcode(opcode) => Error::UnknownOpcode {
+                trie,
+                index,
+                offset,
+                cell,
+                command,
+                opcode,
+            },
+            InvalidPatch::InvalidParameter(param) => Error::InvalidParameter {
+                trie,
+                index,
+                offset,
+                cell,
+                command,
+                param,
+            },
+        }
+    }
+}
+
+#[cfg(all(test, feature = "external"))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_patch() {
+        let commands = Command::parse_patch("Rs-aRiDcIx").unwrap();
+        assert_eq!(
+            commands,
+            [
+                Command::Replace { char: 's' },
+                Command::Skip { chars: 0 },
+                Command::Replace { char: 'i' },
+                Command::Delete { chars: 2 },
+                Command::Insert { char: 'x' },
+            ]
+        );
+        let mut patch = String::new();
+        for cmd in &commands {
+            cmd.push_to(&mut patch);
+        }
+        assert_eq!(patch, "Rs-aRiDcIx");
+        assert_eq!(length_pp(&commands), 6);
+        assert!(Command::parse_patch("").unwrap().is_empty());
+
+        assert_eq!(
+            Command::parse_patch("DcXa"),
+            Err(InvalidPatch::UnknownOpcode('X'))
+        );
+        assert_eq!(
+            Command::parse_patch("D!"),
+            Err(InvalidPatch::InvalidParameter(Some('!')))
+        );
+        assert_eq!(
+            Command::parse_patch("DcR"),
+            Err(InvalidPatch::InvalidParameter(None))
+        );
+        assert!(Command::parse_patch("-\u{200}").is_err());
+    }
+}
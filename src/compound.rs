@@ -0,0 +1,177 @@
+use crate::tokenize::is_hyphen;
+use crate::{Stem, StemmerOptions};
+use std::borrow::Cow;
+
+/// Which parts of a hyphenated compound, like `polsko-niemiecki`, are stemmed by [`Compounds`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum CompoundParts {
+    /// Every part is stemmed on its own, so that e.g. `wschodnio-europejskiemu` stems like `wschodnio` and
+    /// `europejskiemu`.
+    #[default]
+    All,
+    /// Only the last part is stemmed. In Polish compound adjectives, like `biało-czerwonego`, the other parts aren't
+    /// inflected, and stemming them can only get them wrong.
+    Last,
+}
+
+/// A stemmer which splits hyphenated compounds, like `polsko-niemiecki`, and stems their parts on their own,
+/// rejoining them with the same hyphens. Otherwise the table only sees the whole word, whose ending is the ending of
+/// the last part. Words without hyphens are stemmed as they are.
+///
+/// Stemmers wrapped in `Compounds` see the parts, so [`Overrides`](crate::Overrides) inside `Compounds` match parts,
+/// while `Overrides` outside of it match whole compounds.
+///
+/// ```rust
+/// use rstempel::{CompoundParts, Compounds, Stem};
+/// let stemmer = Compounds::new(&rstempel::embedded::STEMMER).parts(CompoundParts::Last);
+/// let stem = rstempel::embedded::STEMMER.stem("czerwonego");
+/// assert_eq!(stemmer.stem("biało-czerwonego"), format!("biało-{}", stem));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Compounds<S> {
+    stemmer: S,
+    parts: CompoundParts,
+}
+
+impl<S> Compounds<S> {
+    /// Returns a stemmer which stems every part of a compound.
+    pub fn new(stemmer: S) -> Self {
+        Self {
+            stemmer,
+            parts: CompoundParts::All,
+        }
+    }
+
+    pub fn parts(mut self, parts: CompoundParts) -> Self {
+        self.parts = parts;
+        self
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.stemmer
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stemmer
+    }
+}
+
+impl<S: Stem> Stem for Compounds<S> {
+    fn stem<'a>(&self, word: &'a str) -> Cow<'a, str> {
+        if !word.contains(is_hyphen) {
+            return self.stemmer.stem(word);
+        }
+        let mut out = String::new();
+        if self.stem_into(word, &mut out) {
+            Cow::Owned(out)
+        } else {
+            Cow::Borrowed(word)
+        }
+    }
+
+    fn stem_into(&self, word: &str, out: &mut String) -> bool {
+        stem_parts(word, out, self.parts, |part, out| {
+            self.stemmer.stem_into(part, out)
+        })
+    }
+
+    fn stem_into_with(&self, word: &str, out: &mut String, options: &StemmerOptions) -> bool {
+        stem_parts(word, out, self.parts, |part, out| {
+            self.stemmer.stem_into_with(part, out, options)
+        })
+    }
+}
+
+/// Stems the `parts` of a hyphenated compound with `stem`, writing them to `out` with the same hyphens, and returns
+/// `true` if any part changed. Empty parts, from leading, trailing or repeated hyphens, are kept.
+pub(crate) fn stem_parts(
+    word: &str,
+    out: &mut String,
+    parts: CompoundParts,
+    mut stem: impl FnMut(&str, &mut String) -> bool,
+) -> bool {
+    if !word.contains(is_hyphen) {
+        return stem(word, out);
+    }
+    out.clear();
+    let mut changed = false;
+    let mut buf = String::new();
+    let mut rest = word;
+    loop {
+        let (part, hyphen) = match rest.char_indices().find(|&(_, ch)| is_hyphen(ch)) {
+            Some((idx, hyphen)) => (&rest[..idx], Some(hyphen)),
+            None => (rest, None),
+        };
+        if part.is_empty() || (parts == CompoundParts::Last && hyphen.is_some()) {
+            out.push_str(part);
+        } else {
+            changed |= stem(part, &mut buf);
+            out.push_str(&buf);
+        }
+        match hyphen {
+            Some(hyphen) => {
+                out.push(hyphen);
+                rest = &rest[part.len() + hyphen.len_utf8()..];
+            }
+            None => return changed,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::Suffix;
+
+    #[test]
+    fn test_compounds() {
+        let stemmer = Compounds::new(Suffix);
+        assert_eq!(stemmer.stem("polsko-niemiecki"), "polskox-niemieckix");
+        assert_eq!(stemmer.stem("kota"), "kotax");
+        assert_eq!(stemmer.stem("-kota--psa\u{2010}"), "-kotax--psax\u{2010}");
+        assert!(matches!(stemmer.stem("--"), Cow::Borrowed("--")));
+
+        let stemmer = stemmer.parts(CompoundParts::Last);
+        assert_eq!(stemmer.stem("biało-czerwony"), "biało-czerwonyx");
+        assert_eq!(stemmer.stem("a-b-kota"), "a-b-kotax");
+        assert!(matches!(stemmer.stem("kota-"), Cow::Borrowed("kota-")));
+
+        // Options apply to each part.
+        let options = StemmerOptions::new().min_length(5);
+        let mut out = String::new();
+        assert!(Compounds::new(Suffix).stem_into_with("e-mailem", &mut out, &options));
+        assert_eq!(out, "e-mailemx");
+        assert!(!Compounds::new(Suffix).stem_into_with("e-mail", &mut out, &options));
+        assert_eq!(out, "e-mail");
+    }
+
+    #[test]
+    fn test_compound_overrides() {
+        use crate::Overrides;
+
+        let mut inner = Overrides::new(Suffix);
+        inner.protect("polsko");
+        let stemmer = Compounds::new(inner);
+        assert_eq!(stemmer.stem("polsko-niemiecki"), "polsko-niemieckix");
+
+        let mut outer = Overrides::new(Compounds::new(Suffix));
+        outer.insert("polsko-niemiecki", "polski");
+        assert_eq!(outer.stem("polsko-niemiecki"), "polski");
+        assert_eq!(outer.stem("polsko-czeski"), "polskox-czeskix");
+    }
+
+    #[cfg(feature = "table_2000")]
+    #[test]
+    fn test_compounds_stemmer() {
+        use crate::embedded::STEMMER;
+
+        let stemmer = Compounds::new(&STEMMER);
+        for (first, last) in [("polsko", "niemieckiego"), ("biało", "czerwonymi")] {
+            assert_eq!(
+                stemmer.stem(&format!("{}-{}", first, last)),
+                format!("{}-{}", STEMMER.stem(first), STEMMER.stem(last))
+            );
+        }
+        assert_eq!(stemmer.stem("kotami"), STEMMER.stem("kotami"));
+    }
+}
@@ -0,0 +1,147 @@
+//! Applies patch commands to a word in place, shared by every stemmer so that stemming doesn't need scratch buffers.
+
+use crate::Command;
+
+/// The position of the current char, which commands move from the end of the word towards its start.
+#[derive(Clone, Copy)]
+enum Cursor {
+    /// The byte index of the current char.
+    At(usize),
+    /// The cursor has moved this many chars before the start of the word.
+    Before(usize),
+}
+
+/// Edits a word in place with the skip, delete, replace and insert commands of a patch.
+pub(crate) struct Editor<'a> {
+    /// The word, until the first edit copies it to `out`.
+    original: Option<&'a str>,
+    out: &'a mut String,
+    cursor: Cursor,
+}
+
+impl<'a> Editor<'a> {
+    /// Starts editing at the last char of `word`.
+    pub fn new(word: &'a mut String) -> Self {
+        Self {
+            cursor: Self::end(word),
+            original: None,
+            out: word,
+        }
+    }
+
+    /// Like [`Editor::new`], but leaves `out` untouched until a command edits the word, so that patches which only
+    /// skip chars, or none at all, don't need a buffer.
+    pub fn lazy(word: &'a str, out: &'a mut String) -> Self {
+        Self {
+            cursor: Self::end(word),
+            original: Some(word),
+            out,
+        }
+    }
+
+    fn end(word: &str) -> Cursor {
+        match word.char_indices().next_back() {
+            Some((idx, _)) => Cursor::At(idx),
+            None => Cursor::Before(1),
+        }
+    }
+
+    /// Returns the word to edit, copying it to `out` if this is the first edit.
+    fn edit(&mut self) -> &mut String {
+        if let Some(original) = self.original.take() {
+            self.out.clear();
+            self.out.push_str(original);
+        }
+        self.out
+    }
+
+    /// True once a command has edited the word, and it's been written to `out`.
+    pub fn is_edited(&self) -> bool {
+        self.original.is_none()
+    }
+
+    /// Moves the cursor `count` chars towards the start of the word.
+    fn back(&mut self, count: usize) {
+        for moved in 0..count {
+            self.cursor = match self.cursor {
+                Cursor::At(0) => Cursor::Before(1),
+                Cursor::At(idx) => {
+                    let prev = self.word()[..idx].chars().next_back().unwrap();
+                    Cursor::At(idx - prev.len_utf8())
+                }
+                Cursor::Before(n) => {
+                    self.cursor = Cursor::Before(n.saturating_add(count - moved));
+                    return;
+                }
+            };
+        }
+    }
+
+    /// Returns the byte range of the current char.
+    fn current(&self) -> Option<std::ops::Range<usize>> {
+        match self.cursor {
+            Cursor::At(idx) => Some(idx..idx + self.word()[idx..].chars().next()?.len_utf8()),
+            Cursor::Before(_) => None,
+        }
+    }
+
+    /// Skips `count` chars.
+    pub fn skip(&mut self, count: usize) {
+        self.back(count);
+    }
+
+    /// Deletes `count` chars, ending with the current one.
+    pub fn delete(&mut self, count: usize) -> Option<()> {
+        let end = self.current()?.end;
+        self.back(count - 1);
+        let start = self.current()?.start;
+        self.edit().drain(start..end);
+        self.back(1);
+        Some(())
+    }
+
+    /// Replaces the current char.
+    pub fn replace(&mut self, ch: char) -> Option<()> {
+        let range = self.current()?;
+        self.edit()
+            .replace_range(range, ch.encode_utf8(&mut [0; 4]));
+        self.back(1);
+        Some(())
+    }
+
+    /// Inserts a char after the current one, which stays current.
+    pub fn insert(&mut self, ch: char) -> Option<()> {
+        let idx = match self.cursor {
+            Cursor::At(_) => self.current()?.end,
+            Cursor::Before(1) => 0,
+            Cursor::Before(_) => return None,
+        };
+        self.edit().insert(idx, ch);
+        Some(())
+    }
+
+    /// Applies a command, returning `None` if it can't be applied.
+    pub fn apply(&mut self, command: Command) -> Option<()> {
+        match command {
+            Command::Skip { chars } => self.skip(usize::from(chars) + 1),
+            Command::Delete { chars } => self.delete(usize::from(chars) + 1)?,
+            Command::Replace { char } => self.replace(char)?,
+            Command::Insert { char } => self.insert(char)?,
+        }
+        Some(())
+    }
+
+    /// Returns the word as edited so far.
+    pub fn word(&self) -> &str {
+        self.original.unwrap_or(self.out)
+    }
+
+    /// Finishes editing, returning `None` if every char was removed.
+    pub fn finish(self) -> Option<()> {
+        if self.word().is_empty() {
+            None
+        } else {
+            Some(())
+        }
+    }
+}
@@ -0,0 +1,263 @@
+//! Converts tables in the format used by the Java `stempel` implementation to the flat layout of [`Stemmer`], either
+//! as Rust code with `generate`, or at runtime as an [`OwnedStemmer`].
+
+use super::*;
+use crate::external::trie::{Patch, Row as JRow, Trie as JTrie};
+use crate::external::{self, Table};
+use crate::Error;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+
+/// The commands of a cell, before they're packed into the narrowest slice type which fits every cell.
+#[derive(Clone, Copy)]
+pub(crate) enum CellCommands {
+    Eom,
+    Slice { index: usize, len: usize },
+}
+
+impl CellCommands {
+    fn pack<C: PackedCommands>(self) -> Option<C> {
+        match self {
+            CellCommands::Eom => Some(C::new_eom()),
+            CellCommands::Slice { index, len } => C::try_new(index, len),
+        }
+    }
+}
+
+pub(crate) struct CellBuilder {
+    pub refr: Option<NonZeroU32>,
+    pub cmds: Option<CellCommands>,
+}
+
+#[derive(Default)]
+pub(crate) struct RowBuilder {
+    pub cells: BTreeMap<char, CellBuilder>,
+}
+
+pub(crate) struct TrieBuilder {
+    pub rows: Vec<RowBuilder>,
+}
+
+/// A table converted to the layout of [`Stemmer`], before its commands are packed.
+#[derive(Default)]
+pub(crate) struct TableBuilder {
+    pub commands: Vec<Command>,
+    command_map: HashMap<Patch, CellCommands>,
+    pub tries: Vec<TrieBuilder>,
+}
+
+impl TableBuilder {
+    /// Converts validated tries, which must read keys from their end.
+    pub fn from_tries(jtries: &[JTrie]) -> Self {
+        let mut table = Self::default();
+        for jtrie in jtries {
+            table.convert_java_trie(jtrie);
+        }
+        table
+    }
+
+    fn convert_java_trie(&mut self, jtrie: &JTrie) {
+        let mut trie = TrieBuilder {
+            rows: Vec::with_capacity(jtrie.rows.len()),
+        };
+        for cmds in &jtrie.cmds {
+            let cs = self.convert_java_command(cmds);
+            self.command_map.insert(cmds.clone(), cs);
+        }
+        // Lookups in the embedded table start at the first row, so move the root there.
+        let root = jtrie.root;
+        let position = |r: u32| match r.cmp(&root) {
+            Ordering::Equal => 0,
+            Ordering::Less => r + 1,
+            Ordering::Greater => r,
+        };
+        let order =
+            std::iter::once(root).chain((0..jtrie.rows.len() as u32).filter(|&r| r != root));
+        for r in order {
+            let row = self.convert_java_row(jtrie, &jtrie.rows[r as usize], position);
+            trie.rows.push(row);
+        }
+        self.tries.push(trie);
+    }
+
+    fn convert_java_command(&mut self, cmds: &Patch) -> CellCommands {
+        if let Some(&cs) = self.command_map.get(cmds) {
+            return cs;
+        }
+        match cmds {
+            Patch::End => CellCommands::Eom,
+            Patch::Commands(commands) => {
+                let index = self.commands.len();
+                self.commands.extend_from_slice(commands);
+                CellCommands::Slice {
+                    index,
+                    len: commands.len(),
+                }
+            }
+        }
+    }
+
+    /// Converts a row of a validated trie, where `position` maps each row reference to its new index.
+    fn convert_java_row(
+        &self,
+        jtrie: &JTrie,
+        row: &JRow,
+        position: impl Fn(u32) -> u32,
+    ) -> RowBuilder {
+        let mut result = RowBuilder::default();
+        for (&ch, cell) in &row.cells {
+            let refr = cell.refr.and_then(|r| NonZeroU32::new(position(r) + 1));
+            let cmds = cell
+                .cmd
+                .and_then(|idx| jtrie.cmds.get(idx as usize))
+                .and_then(|cmd| self.command_map.get(cmd))
+                .cloned();
+            result.cells.insert(ch, CellBuilder { refr, cmds });
+        }
+        result
+    }
+
+    /// True if some cell's commands don't fit in a [`CommandSlice`], so the table needs [`WideCommandSlice`]s.
+    #[cfg(feature = "generate")]
+    pub fn needs_wide_commands(&self) -> bool {
+        self.command_map.values().any(|cmds| match *cmds {
+            CellCommands::Eom => false,
+            CellCommands::Slice { index, len } => CommandSlice::try_new(index, len).is_none(),
+        })
+    }
+
+    /// Packs the table into an [`OwnedStemmer`], or returns `None` if some cell's commands don't fit in `C`.
+    fn build<C: PackedCommands>(&self) -> Option<OwnedStemmer<C>> {
+        let row = |row: &RowBuilder| {
+            let cells = row
+                .cells
+                .values()
+                .map(|cell| {
+                    let cmds = match cell.cmds {
+                        Some(cmds) => Some(cmds.pack()?),
+                        None => None,
+                    };
+                    Some(Cell {
+                        refr: cell.refr,
+                        cmds,
+                    })
+                })
+                .collect::<Option<_>>()?;
+            Some(Row {
+                cells,
+                chars: row.cells.keys().copied().collect(),
+            })
+        };
+        let tries = self
+            .tries
+            .iter()
+            .map(|trie| {
+                let rows = trie.rows.iter().map(row).collect::<Option<_>>()?;
+                Some(Trie { rows })
+            })
+            .collect::<Option<_>>()?;
+        Some(Stemmer {
+            commands: self.commands.as_slice().into(),
+            tries,
+        })
+    }
+}
+
+/// Converts a table loaded at runtime to the layout of the embedded tables, which is faster to look up. Returns
+/// [`Error::UnsupportedMethod`] for tables which read words from their start, which the layout doesn't support, or
+/// [`Error::TooManyCommands`] if `C` can't refer to every cell's commands.
+impl<C: PackedCommands> TryFrom<&external::Stemmer> for OwnedStemmer<C> {
+    type Error = Error;
+
+    fn try_from(stemmer: &external::Stemmer) -> Result<Self, Error> {
+        // A single trie is looked up like the first trie of a multi-trie, whose commands are all applied.
+        let (forward, tries) = match stemmer.table() {
+            Table::Trie(trie) => (trie.forward, std::slice::from_ref(trie)),
+            Table::MultiTrie2(multi) => (multi.t.forward, multi.t.tries.as_slice()),
+        };
+        if forward || tries.iter().any(|trie| trie.forward) {
+            return Err(Error::UnsupportedMethod(stemmer.method().into()));
+        }
+        stemmer.table().validate()?;
+        TableBuilder::from_tries(tries)
+            .build()
+            .ok_or(Error::TooManyCommands)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::external::multitrie::MultiTrie2;
+    use crate::test_util::load_stemmer;
+    use crate::Stem;
+
+    const WORDS: &[&str] = &[
+        "kotami",
+        "książkami",
+        "czytałem",
+        "robiliśmy",
+        "ludziom",
+        "mieszkanie",
+        "warszawskiego",
+        "prezydenta",
+        "dzieciom",
+        "samochodów",
+        "zdzie",
+        "oczach",
+        "pięknymi",
+        "Abadan",
+        "kot",
+        "zzzz",
+    ];
+
+    #[test]
+    fn test_convert_external() {
+        let external = load_stemmer();
+        let stemmer = OwnedStemmer::<CommandSlice>::try_from(&external).unwrap();
+        let options = StemmerOptions::new().max_stages(2);
+        for word in WORDS {
+            assert_eq!(stemmer.stem(word), external.stem(word), "{}", word);
+            assert_eq!(stemmer.command_for(word), external.command_for(word));
+            assert_eq!(
+                options.wrap(&stemmer).stem(word),
+                options.wrap(&external).stem(word)
+            );
+            #[cfg(feature = "table_2000")]
+            assert_eq!(stemmer.stem_explain(word), STEMMER.stem_explain(word));
+        }
+    }
+
+    #[cfg(feature = "train")]
+    #[test]
+    fn test_convert_single_trie() {
+        let mut trainer = crate::train::Trainer::new("-");
+        trainer.add_stem("kot", ["kota", "kotami", "kotem"]);
+        trainer.add("psami", "pies");
+        let external = trainer.build();
+        let stemmer = OwnedStemmer::<CommandSlice>::try_from(&external).unwrap();
+        for word in ["kota", "kotami", "kotem", "psami", "psem", "kot"] {
+            assert_eq!(stemmer.stem(word), external.stem(word), "{}", word);
+        }
+
+        let forward = crate::train::Trainer::new("M").build();
+        assert!(matches!(
+            OwnedStemmer::<CommandSlice>::try_from(&forward),
+            Err(Error::UnsupportedMethod(method)) if method == "M"
+        ));
+    }
+
+    #[test]
+    fn test_convert_wide() {
+        let mut table = MultiTrie2::new(false);
+        table.patch("kota", &[Command::Insert { char: 'x' }; 20]);
+        let external = external::Stemmer::new("-M", Table::MultiTrie2(table));
+        assert!(matches!(
+            OwnedStemmer::<CommandSlice>::try_from(&external),
+            Err(Error::TooManyCommands)
+        ));
+        let stemmer = OwnedStemmer::<WideCommandSlice>::try_from(&external).unwrap();
+        assert_eq!(stemmer.stem("kota"), external.stem("kota"));
+        assert_eq!(stemmer.stem("kota"), format!("kota{}", "x".repeat(20)));
+    }
+}
@@ -1,129 +1,152 @@
+use super::convert::{CellBuilder, CellCommands, RowBuilder, TableBuilder, TrieBuilder};
 use super::*;
 use crate::external::multitrie::MultiTrie2;
 use crate::external::serialize::JavaDeserialize;
-use crate::external::trie::{Row as JRow, Trie as JTrie};
-use std::collections::{BTreeMap, HashMap};
+use crate::Error;
 use std::io;
 
-#[derive(Default)]
-struct RowBuilder {
-    cells: BTreeMap<char, Cell>,
-}
-
-struct TrieBuilder {
-    rows: Vec<RowBuilder>,
-}
-
-#[derive(Default)]
 pub struct RustGenerator {
-    commands: Vec<Command>,
-    command_map: HashMap<String, CommandSlice>,
-    tries: Vec<TrieBuilder>,
+    table: TableBuilder,
 }
 
 impl RustGenerator {
-    pub fn load_java_table(input: impl io::Read) -> io::Result<Self> {
+    /// Reads a table in the format used by the Java `stempel` implementation, which must use a [`MultiTrie2`].
+    pub fn load_java_table(input: impl io::Read) -> Result<Self, Error> {
         use crate::external::serialize::DataInput;
 
         let mut input = DataInput::new(input);
-        let _ = input.read_string()?;
-        let input = MultiTrie2::deserialize(&mut input)?;
-        let gen = Self::convert_java_multitrie(&input);
-        Ok(gen)
-    }
-
-    pub fn convert_java_multitrie(jmultitrie: &MultiTrie2) -> Self {
-        let mut gen = Self::default();
-        for jtrie in &jmultitrie.t.tries {
-            gen.convert_java_trie(jtrie);
+        let method = input.read_string()?;
+        if !method.contains(['M', 'm']) {
+            return Err(Error::UnsupportedMethod(method));
         }
-        gen
-    }
-
-    fn convert_java_trie(&mut self, jtrie: &JTrie) {
-        let mut trie = TrieBuilder {
-            rows: Vec::with_capacity(jtrie.rows.len()),
-        };
-        for cmds in &jtrie.cmds {
-            let cs = self.convert_java_command(cmds);
-            self.command_map.insert(cmds.into(), cs);
-        }
-        for jrow in &jtrie.rows {
-            let row = self.convert_java_row(jtrie, jrow);
-            trie.rows.push(row);
-        }
-        self.tries.push(trie);
-    }
-
-    fn convert_java_command(&mut self, cmds: &str) -> CommandSlice {
-        if cmds == "*" {
-            return CommandSlice::new_eom();
-        } else if let Some(&cs) = self.command_map.get(cmds) {
-            return cs;
-        }
-        debug_assert!(cmds.chars().count() % 2 == 0);
-        let mut chars = cmds.chars();
-        let idx = self.commands.len();
-        while let (Some(cmd), Some(param)) = (chars.next(), chars.next()) {
-            let cmd = Command::parse(cmd, param)
-                .unwrap_or_else(|| panic!("Unable to parse command: \"{}{}\"", cmd, param));
-            self.commands.push(cmd);
-        }
-        let len = self.commands.len() - idx;
-        CommandSlice::new(idx, len)
+        let input = MultiTrie2::deserialize(&mut input)?;
+        Self::convert_java_multitrie(&input)
     }
 
-    fn convert_java_row(&mut self, jtrie: &JTrie, row: &JRow) -> RowBuilder {
-        let mut result = RowBuilder::default();
-        for (&ch, cell) in &row.cells {
-            let refr = cell.refr.map(|r| {
-                NonZeroU16::new((r + 1).try_into().expect("Row index did not fit in u16")).unwrap()
-            });
-            let cmds = cell
-                .cmd
-                .and_then(|idx| jtrie.cmds.get(idx as usize))
-                .and_then(|cmd| self.command_map.get(cmd))
-                .cloned();
-            result.cells.insert(ch, Cell { refr, cmds });
-        }
-        result
+    /// Converts a table after checking it with [`MultiTrie2::validate`].
+    pub fn convert_java_multitrie(jmultitrie: &MultiTrie2) -> Result<Self, Error> {
+        jmultitrie.validate()?;
+        Ok(Self {
+            table: TableBuilder::from_tries(&jmultitrie.t.tries),
+        })
     }
 
+    /// Writes the table as a Rust `static`, using [`CommandSlice`]s unless it needs [`WideCommandSlice`]s.
     pub fn write_rust_table(&self, mut out: impl io::Write) -> io::Result<()> {
         use std::mem::size_of;
-        writeln!(out, "use std::num::{{NonZeroU16, NonZeroU32}};")?;
-        writeln!(
-            out,
-            "use crate::embedded::{{Cell, Command, CommandSlice, Row, Stemmer, Trie}};\n"
-        )?;
-        let num_rows: usize = self.tries.iter().map(|t| t.rows.len()).sum();
+        let wide = self.table.needs_wide_commands();
+        if wide {
+            writeln!(out, "use std::num::{{NonZeroU32, NonZeroU64}};")?;
+            writeln!(
+                out,
+                "use crate::embedded::{{Cell, Command, Row, Stemmer, Trie, WideCommandSlice}};\n"
+            )?;
+        } else {
+            writeln!(out, "use std::num::NonZeroU32;")?;
+            writeln!(
+                out,
+                "use crate::embedded::{{Cell, Command, CommandSlice, Row, Stemmer, Trie}};\n"
+            )?;
+        }
+        let num_rows: usize = self.table.tries.iter().map(|t| t.rows.len()).sum();
         let num_cells: usize = self
+            .table
             .tries
             .iter()
             .flat_map(|t| &t.rows)
             .map(|r| r.cells.len())
             .sum();
+        let cell_size = if wide {
+            size_of::<Cell<WideCommandSlice>>()
+        } else {
+            size_of::<Cell>()
+        };
         let size = size_of::<Stemmer>()
-            + self.tries.len() * size_of::<Trie>()
+            + self.table.tries.len() * size_of::<Trie>()
             + num_rows * size_of::<Row>()
-            + num_cells * (size_of::<Cell>() + size_of::<char>())
-            + self.commands.len() * size_of::<Command>();
+            + num_cells * (cell_size + size_of::<char>())
+            + self.table.commands.len() * size_of::<Command>();
         writeln!(out, "// approximate size: {} bytes", size)?;
-        writeln!(out, "pub static STEMMER: Stemmer = Stemmer {{")?;
+        if wide {
+            writeln!(
+                out,
+                "pub static STEMMER: Stemmer<WideCommandSlice> = Stemmer {{"
+            )?;
+        } else {
+            writeln!(out, "pub static STEMMER: Stemmer = Stemmer {{")?;
+        }
         writeln!(out, "commands: &[")?;
-        for command in &self.commands {
+        for command in &self.table.commands {
             Self::write_rust_command(&mut out, command)?;
         }
         writeln!(out, "],")?;
         writeln!(out, "tries: &[")?;
-        for trie in &self.tries {
-            Self::write_rust_trie(&mut out, trie)?;
+        for trie in &self.table.tries {
+            Self::write_rust_trie(&mut out, trie, wide)?;
         }
         writeln!(out, "],")?;
         writeln!(out, "}};")?;
         Ok(())
     }
 
+    /// Writes the table in the format read by [`crate::binary::Stemmer`]. Returns
+    /// [`std::io::ErrorKind::InvalidInput`] if the table needs [`WideCommandSlice`]s, which the format doesn't support.
+    pub fn write_binary_table(&self, mut out: impl io::Write) -> io::Result<()> {
+        use crate::binary::{encode_command, MAGIC, VERSION};
+
+        fn write(out: &mut impl io::Write, value: usize) -> io::Result<()> {
+            let value =
+                u32::try_from(value).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+            out.write_all(&value.to_le_bytes())
+        }
+
+        if self.table.needs_wide_commands() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "table has too many commands for the binary format",
+            ));
+        }
+
+        let rows = || self.table.tries.iter().flat_map(|t| &t.rows);
+        let num_cells: usize = rows().map(|r| r.cells.len()).sum();
+        out.write_all(&MAGIC)?;
+        for value in [
+            VERSION as usize,
+            self.table.commands.len(),
+            self.table.tries.len(),
+            rows().count(),
+            num_cells,
+        ] {
+            write(&mut out, value)?;
+        }
+        for &command in &self.table.commands {
+            write(&mut out, encode_command(command) as usize)?;
+        }
+        let mut first_row = 0;
+        for trie in &self.table.tries {
+            write(&mut out, first_row)?;
+            first_row += trie.rows.len();
+        }
+        let mut first_cell = 0;
+        for row in rows() {
+            write(&mut out, first_cell)?;
+            write(&mut out, row.cells.len())?;
+            first_cell += row.cells.len();
+        }
+        for &ch in rows().flat_map(|r| r.cells.keys()) {
+            write(&mut out, ch as usize)?;
+        }
+        for cell in rows().flat_map(|r| r.cells.values()) {
+            write(&mut out, cell.refr.map_or(0, |r| r.get() as usize))?;
+            let cmds = cell.cmds.map_or(0, |cmds| match cmds {
+                CellCommands::Eom => CommandSlice::new_eom().0.get(),
+                CellCommands::Slice { index, len } => CommandSlice::new(index, len).0.get(),
+            });
+            write(&mut out, cmds as usize)?;
+        }
+        Ok(())
+    }
+
     fn write_rust_command(mut out: impl io::Write, cmd: &Command) -> io::Result<()> {
         match cmd {
             Command::Skip { chars } => {
@@ -141,20 +164,20 @@ impl RustGenerator {
         }
     }
 
-    fn write_rust_trie(mut out: impl io::Write, trie: &TrieBuilder) -> io::Result<()> {
+    fn write_rust_trie(mut out: impl io::Write, trie: &TrieBuilder, wide: bool) -> io::Result<()> {
         writeln!(out, "Trie {{ rows: &[")?;
         for row in &trie.rows {
-            Self::write_rust_row(&mut out, row)?;
+            Self::write_rust_row(&mut out, row, wide)?;
         }
         writeln!(out, "] }},")?;
         Ok(())
     }
 
-    fn write_rust_row(mut out: impl io::Write, row: &RowBuilder) -> io::Result<()> {
+    fn write_rust_row(mut out: impl io::Write, row: &RowBuilder, wide: bool) -> io::Result<()> {
         writeln!(out, "Row {{")?;
         writeln!(out, "cells: &[")?;
         for cell in row.cells.values() {
-            Self::write_rust_cell(&mut out, cell)?;
+            Self::write_rust_cell(&mut out, cell, wide)?;
         }
         writeln!(out, "],")?;
         writeln!(out, "chars: &[")?;
@@ -169,23 +192,29 @@ impl RustGenerator {
         Ok(())
     }
 
-    fn write_rust_cell(mut out: impl io::Write, cell: &Cell) -> io::Result<()> {
+    fn write_rust_cell(mut out: impl io::Write, cell: &CellBuilder, wide: bool) -> io::Result<()> {
         write!(out, "Cell {{ refr: ")?;
         match cell.refr {
             Some(refr) => write!(
                 out,
-                "Some(unsafe {{ NonZeroU16::new_unchecked({}) }})",
+                "Some(unsafe {{ NonZeroU32::new_unchecked({}) }})",
                 refr
             )?,
             None => write!(out, "None")?,
         };
         write!(out, ", cmds: ")?;
         match cell.cmds {
-            Some(cmds) if cmds.is_eom() => write!(out, "Some(CommandSlice::new_eom())")?,
-            Some(cmds) => write!(
+            Some(CellCommands::Eom) if wide => write!(out, "Some(WideCommandSlice::new_eom())")?,
+            Some(CellCommands::Eom) => write!(out, "Some(CommandSlice::new_eom())")?,
+            Some(CellCommands::Slice { index, len }) if wide => write!(
+                out,
+                "Some(WideCommandSlice(unsafe {{ NonZeroU64::new_unchecked({}) }}))",
+                WideCommandSlice::new(index, len).0.get()
+            )?,
+            Some(CellCommands::Slice { index, len }) => write!(
                 out,
                 "Some(CommandSlice(unsafe {{ NonZeroU32::new_unchecked({}) }}))",
-                cmds.0.get()
+                CommandSlice::new(index, len).0.get()
             )?,
             None => write!(out, "None")?,
         };
@@ -193,3 +222,87 @@ impl RustGenerator {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::external::trie::Trie as JTrie;
+
+    #[test]
+    fn test_wide_tables() {
+        // A long key needs a row per char, and a long patch can't be packed in a `CommandSlice`.
+        let key = "a".repeat(70_000);
+        let cmd = [Command::Insert { char: 'x' }; 20];
+        let mut jmultitrie = MultiTrie2::new(false);
+        jmultitrie.patch(&key, &cmd);
+        let gen = RustGenerator::convert_java_multitrie(&jmultitrie).unwrap();
+        assert!(gen.table.tries[0].rows.len() > usize::from(u16::MAX));
+        assert!(gen.table.needs_wide_commands());
+
+        let mut rust = Vec::new();
+        gen.write_rust_table(&mut rust).unwrap();
+        let rust = String::from_utf8(rust).unwrap();
+        assert!(rust.contains("pub static STEMMER: Stemmer<WideCommandSlice> = Stemmer {"));
+        assert!(rust.contains("NonZeroU32::new_unchecked(70000)"));
+        assert!(!rust.contains("Some(CommandSlice"));
+
+        let err = gen.write_binary_table(io::sink()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_conversion_errors() {
+        let dc = [Command::Delete { chars: 2 }];
+        let mut single = Vec::new();
+        let mut trie = JTrie::new(false);
+        trie.add("kotami", &dc[..]);
+        crate::external::Stemmer::new("-E", crate::external::Table::Trie(trie))
+            .save(&mut single)
+            .unwrap();
+        assert!(matches!(
+            RustGenerator::load_java_table(single.as_slice()),
+            Err(Error::UnsupportedMethod(method)) if method == "-E"
+        ));
+
+        let mut jmultitrie = MultiTrie2::new(false);
+        jmultitrie.add("kotami", &dc);
+
+        let mut bad_ref = jmultitrie.clone();
+        let trie = &mut bad_ref.t.tries[1];
+        let num_rows = trie.rows.len() as u32;
+        let root = trie.root as usize;
+        trie.rows[root].cells.get_mut(&'i').unwrap().refr = Some(num_rows);
+        assert!(matches!(
+            RustGenerator::convert_java_multitrie(&bad_ref),
+            Err(Error::InvalidRowReference { trie: 1, row, cell: 'i' }) if row == root
+        ));
+
+        // Commands are parsed while loading the table, so replace the patch string `Dc` in a saved table.
+        let mut saved = Vec::new();
+        crate::external::Stemmer::new("-M", crate::external::Table::MultiTrie2(jmultitrie))
+            .save(&mut saved)
+            .unwrap();
+        let load_with = |patch: &[u8; 2]| {
+            let mut saved = saved.clone();
+            let idx = saved.windows(4).position(|w| w == b"\0\x02Dc").unwrap();
+            saved[idx + 2..idx + 4].copy_from_slice(patch);
+            RustGenerator::load_java_table(saved.as_slice())
+        };
+        assert!(matches!(
+            load_with(b"Xa"),
+            Err(Error::UnknownOpcode {
+                trie: 0,
+                opcode: 'X',
+                ..
+            })
+        ));
+        assert!(matches!(
+            load_with(b"D!"),
+            Err(Error::InvalidParameter {
+                trie: 0,
+                param: Some('!'),
+                ..
+            })
+        ));
+    }
+}
@@ -1,185 +1,266 @@
 use std::{
     borrow::Cow,
-    num::{NonZeroU16, NonZeroU32},
+    num::{NonZeroU32, NonZeroU64},
+    ops::Deref,
 };
 
-use crate::Stem;
+use crate::edit::Editor;
+use crate::explain::{Decision, Explanation, Trace, Tracer};
+use crate::normalize;
+use crate::{Stem, StemmerOptions};
 
 #[cfg(feature = "table_2000")]
 #[path = "../tables/stemmer_2000.out.rs"]
+#[allow(clippy::all)]
 mod generated_stemmer;
 
 #[cfg(feature = "table_2000")]
 pub use generated_stemmer::STEMMER;
 
+#[cfg(feature = "external")]
+mod convert;
+
 #[cfg(feature = "generate")]
 pub mod generate;
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
-pub enum Command {
-    Skip { chars: u8 },
-    Delete { chars: u8 },
-    Replace { char: char },
-    Insert { char: char },
+pub use crate::Command;
+
+/// The packed index and length of a slice of commands in the Stemmer's commands vec, or an EndOfMultiTrie marker.
+///
+/// Tables use [`CommandSlice`], or [`WideCommandSlice`] if they have too many commands for it, which doubles the size
+/// of each [`Cell`].
+#[allow(clippy::len_without_is_empty)]
+pub trait PackedCommands: Copy {
+    /// Returns an EndOfMultiTrie marker.
+    fn new_eom() -> Self;
+    /// Packs the slice, or returns `None` if it doesn't fit.
+    fn try_new(index: usize, len: usize) -> Option<Self>;
+    /// True if this is an EndOfMultiTrie marker.
+    fn is_eom(self) -> bool;
+    fn len(self) -> usize;
+    fn start_index(self) -> usize;
 }
 
-impl std::fmt::Display for Command {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match *self {
-            Command::Skip { chars } => write!(f, "SKP {}", chars),
-            Command::Delete { chars } => write!(f, "DEL {}", chars),
-            Command::Replace { char } => write!(f, "SET {}", char),
-            Command::Insert { char } => write!(f, "INS {}", char),
-        }
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[repr(transparent)]
+/// Represents a slice of commands in the Stemmer's commands vec, packed into a u32.
+pub struct CommandSlice(pub NonZeroU32);
+
+impl CommandSlice {
+    #[must_use]
+    pub const fn new_eom() -> Self {
+        Self(unsafe { NonZeroU32::new_unchecked(u32::MAX) })
     }
-}
 
-impl Command {
-    pub fn parse(cmd: char, param: char) -> Option<Self> {
-        match cmd {
-            '-' => {
-                assert!(param.is_ascii_lowercase());
-                let chars = (param as u8) - b'a';
-                Some(Self::Skip { chars })
-            }
-            'D' => {
-                assert!(param.is_ascii_lowercase());
-                let chars = (param as u8) - b'a';
-                Some(Self::Delete { chars })
-            }
-            'R' => Some(Self::Replace { char: param }),
-            'I' => Some(Self::Insert { char: param }),
-            _ => None,
+    #[must_use]
+    pub fn new(index: usize, len: usize) -> Self {
+        Self::try_new(index, len).expect("Command slice did not fit in u32")
+    }
+
+    /// Packs the slice, or returns `None` if it needs a [`WideCommandSlice`].
+    #[must_use]
+    pub fn try_new(index: usize, len: usize) -> Option<Self> {
+        if len == 0 || len > 0xF || index >= (1 << 24) {
+            return None;
         }
+        let packed = (index as u32) << 4 | (len as u32);
+        Some(Self(NonZeroU32::new(packed).unwrap()))
     }
 
-    fn cannot_follow(&self, prev: Command) -> bool {
-        matches!(
-            (&self, prev),
-            (Command::Skip { .. }, Command::Skip { .. })
-                | (Command::Delete { .. }, Command::Delete { .. })
-        )
+    /// True if this is an EndOfMultiTrie marker.
+    #[must_use]
+    pub fn is_eom(self) -> bool {
+        self.0.get() == u32::MAX
     }
 
-    fn is_skip(&self) -> bool {
-        matches!(*self, Command::Skip { .. })
+    #[allow(clippy::len_without_is_empty)]
+    #[must_use]
+    pub fn len(self) -> usize {
+        debug_assert!(!self.is_eom());
+        (self.0.get() & 0xF) as usize
     }
-    #[allow(dead_code)]
-    fn is_delete(&self) -> bool {
-        matches!(*self, Command::Delete { .. })
+
+    #[must_use]
+    pub fn start_index(self) -> usize {
+        debug_assert!(!self.is_eom());
+        (self.0.get() >> 4) as usize
     }
-    #[allow(dead_code)]
-    fn is_replace(&self) -> bool {
-        matches!(*self, Command::Replace { .. })
+
+    #[must_use]
+    pub fn lookup(self, commands: &[Command]) -> &[Command] {
+        debug_assert!(!self.is_eom());
+        let idx = self.start_index();
+        let len = self.len();
+        &commands[idx..(idx + len)]
     }
-    #[allow(dead_code)]
-    fn is_insert(&self) -> bool {
-        matches!(*self, Command::Insert { .. })
+}
+
+impl PackedCommands for CommandSlice {
+    fn new_eom() -> Self {
+        Self::new_eom()
     }
 
-    fn length_pp(&self) -> usize {
-        match *self {
-            Command::Skip { chars } | Command::Delete { chars } => chars as usize + 1,
-            Command::Replace { .. } => 1,
-            Command::Insert { .. } => 0,
-        }
+    fn try_new(index: usize, len: usize) -> Option<Self> {
+        Self::try_new(index, len)
+    }
+
+    fn is_eom(self) -> bool {
+        self.is_eom()
+    }
+
+    fn len(self) -> usize {
+        self.len()
+    }
+
+    fn start_index(self) -> usize {
+        self.start_index()
     }
 }
 
 #[derive(Clone, Copy, PartialEq, Eq)]
 #[repr(transparent)]
-/// Represents a slice of commands in the Stemmer's commands vec, packed into a u32.
-pub struct CommandSlice(pub NonZeroU32);
+/// Represents a slice of commands in the Stemmer's commands vec, packed into a u64, for tables with more commands or
+/// longer command lists than a [`CommandSlice`] can refer to.
+pub struct WideCommandSlice(pub NonZeroU64);
 
-impl CommandSlice {
+impl WideCommandSlice {
     #[must_use]
     pub const fn new_eom() -> Self {
-        Self(unsafe { NonZeroU32::new_unchecked(u32::MAX) })
+        Self(unsafe { NonZeroU64::new_unchecked(u64::MAX) })
     }
 
     #[must_use]
     pub fn new(index: usize, len: usize) -> Self {
-        assert!(len > 0 && len <= 0xF);
-        assert!(index < (1 << 24));
-        let packed = (index as u32) << 4 | (len as u32);
-        assert!(packed != u32::MAX);
-        Self(NonZeroU32::new(packed).unwrap())
+        Self::try_new(index, len).expect("Command slice did not fit in u64")
+    }
+
+    /// Packs the slice, or returns `None` if it's empty or too long, or the index is too large.
+    #[must_use]
+    pub fn try_new(index: usize, len: usize) -> Option<Self> {
+        if len == 0 || len > 0xFFFF || (index as u64) >= (1 << 48) {
+            return None;
+        }
+        let packed = (index as u64) << 16 | (len as u64);
+        Some(Self(NonZeroU64::new(packed).unwrap()))
     }
 
     /// True if this is an EndOfMultiTrie marker.
     #[must_use]
     pub fn is_eom(self) -> bool {
-        self.0.get() == u32::MAX
+        self.0.get() == u64::MAX
     }
 
     #[allow(clippy::len_without_is_empty)]
     #[must_use]
     pub fn len(self) -> usize {
         debug_assert!(!self.is_eom());
-        (self.0.get() & 0xF) as usize
+        (self.0.get() & 0xFFFF) as usize
     }
 
     #[must_use]
     pub fn start_index(self) -> usize {
         debug_assert!(!self.is_eom());
-        (self.0.get() >> 4) as usize
+        (self.0.get() >> 16) as usize
     }
+}
 
-    #[must_use]
-    pub fn lookup(self, commands: &[Command]) -> &[Command] {
-        debug_assert!(!self.is_eom());
-        let idx = self.start_index();
-        let len = self.len();
-        &commands[idx..(idx + len)]
+impl PackedCommands for WideCommandSlice {
+    fn new_eom() -> Self {
+        Self::new_eom()
+    }
+
+    fn try_new(index: usize, len: usize) -> Option<Self> {
+        Self::try_new(index, len)
+    }
+
+    fn is_eom(self) -> bool {
+        self.is_eom()
+    }
+
+    fn len(self) -> usize {
+        self.len()
+    }
+
+    fn start_index(self) -> usize {
+        self.start_index()
     }
 }
 
 #[derive(Clone, Copy, PartialEq, Eq)]
-pub struct Cell {
+pub struct Cell<C: 'static = CommandSlice> {
     /// A reference to the next row in the trie, if present.
     /// With this + the next char in the word you can find the next cell.
-    /// The row index starts at 1 so we can use the NonZeroU16 + Option size optimization.
-    pub refr: Option<NonZeroU16>,
+    /// The row index starts at 1 so we can use the NonZeroU32 + Option size optimization. A narrower index wouldn't
+    /// make the cell any smaller, because of the alignment of `cmds`.
+    pub refr: Option<NonZeroU32>,
     /// An index+len of commands in the Stemmer's command vec, or an EndOfMultiTrie marker.
-    pub cmds: Option<CommandSlice>,
+    pub cmds: Option<C>,
+}
+
+/// How the tables of a [`Stemmer`] are stored: [`Static`] for tables compiled into the program, or [`Owned`] for tables
+/// built at runtime.
+pub trait Storage: 'static {
+    type Slice<T: 'static>: Deref<Target = [T]>;
+}
+
+/// Tables stored in `&'static` slices, like those written by `generate`.
+pub struct Static;
+
+impl Storage for Static {
+    type Slice<T: 'static> = &'static [T];
+}
+
+/// Tables stored in boxed slices, like those converted from an `external::Stemmer`.
+pub struct Owned;
+
+impl Storage for Owned {
+    type Slice<T: 'static> = Box<[T]>;
 }
 
-#[derive(Clone, Copy)]
 /// A row is basically a `Map<char, Cell>`.
-pub struct Row {
+pub struct Row<C: 'static = CommandSlice, S: Storage = Static> {
     /// List of cell values. Each cell's `char` key is at the corresponding index in `chars`.
-    pub cells: &'static [Cell],
+    pub cells: S::Slice<Cell<C>>,
     /// Sorted list of `char`s, used to lookup the matching index of the cell.
     /// Stored separately from cells for better cache efficiency during lookup.
-    pub chars: &'static [char],
+    pub chars: S::Slice<char>,
+}
+
+impl<C: Copy> Clone for Row<C, Static> {
+    fn clone(&self) -> Self {
+        *self
+    }
 }
 
-impl Row {
-    pub fn get(&self, ch: char) -> Option<&Cell> {
+impl<C: Copy> Copy for Row<C, Static> {}
+
+impl<C, S: Storage> Row<C, S> {
+    pub fn get(&self, ch: char) -> Option<&Cell<C>> {
         let idx = self.chars.binary_search(&ch).ok()?;
         Some(&self.cells[idx])
     }
 }
 
-pub struct Trie {
-    pub rows: &'static [Row],
+pub struct Trie<C: 'static = CommandSlice, S: Storage = Static> {
+    pub rows: S::Slice<Row<C, S>>,
 }
 
-impl Trie {
-    fn get(&self, word: &[char]) -> Option<CommandSlice> {
-        let mut row = self.rows[0];
+impl<C: PackedCommands, S: Storage> Trie<C, S> {
+    fn get(&self, word: &str, trace: &mut impl Trace<C>) -> Option<C> {
+        let mut idx = 0;
         let mut last = None;
-        for (i, &ch) in word.iter().rev().enumerate() {
-            if let Some(cell) = row.get(ch) {
+        let mut chars = word.chars().rev().peekable();
+        while let Some(ch) = chars.next() {
+            if let Some(cell) = self.rows[idx].get(ch) {
+                trace.step(idx, ch, cell.cmds.is_some());
                 if let Some(cmds) = cell.cmds {
                     last = Some(cmds);
                 }
-                if i == word.len() - 1 {
+                if chars.peek().is_none() {
                     break; // Don't check references on last char
                 }
                 if let Some(next_row) = cell.refr {
-                    let next_row = (next_row.get() - 1) as usize;
-                    row = self.rows[next_row];
+                    idx = (next_row.get() - 1) as usize;
                 } else {
                     break;
                 }
@@ -191,100 +272,248 @@ impl Trie {
     }
 }
 
-pub struct Stemmer {
+/// A stemmer which uses tables in a flat layout which is fast to look up. Tables are usually embedded in the program
+/// as `static`s, such as `STEMMER`, but with the `external` feature a table loaded at runtime can also be converted to
+/// this layout, see `OwnedStemmer`.
+pub struct Stemmer<C: 'static = CommandSlice, S: Storage = Static> {
     /// Flattened list of deduplicated command lists.
-    commands: &'static [Command],
-    tries: &'static [Trie],
+    commands: S::Slice<Command>,
+    tries: S::Slice<Trie<C, S>>,
 }
 
-impl Stem for Stemmer {
-    fn stem<'a>(&self, word: &'a str) -> Cow<'a, str> {
-        if word.chars().count() <= 3 {
-            return Cow::Borrowed(word);
-        }
-        let result = word.chars().collect::<Vec<char>>();
-        let cmds = match self.get_cmd(&result) {
-            Some(c) => c,
-            None => return Cow::Borrowed(word),
-        };
-        apply_edits(result, &cmds).map_or(Cow::Borrowed(word), Cow::from)
+/// A [`Stemmer`] which owns its tables, converted from an [`external::Stemmer`](crate::external::Stemmer) with
+/// `try_from` to speed up lookups. Tables with too many commands for a [`CommandSlice`] need an
+/// `OwnedStemmer<WideCommandSlice>`.
+///
+/// ```rust,no_run
+/// use rstempel::{embedded::OwnedStemmer, external, Stem};
+/// let file = std::io::BufReader::new(std::fs::File::open("stemmer.tbl")?);
+/// let stemmer: OwnedStemmer = OwnedStemmer::try_from(&external::Stemmer::load(file)?)?;
+/// println!("{}", stemmer.stem("kotami"));
+/// # Ok::<(), rstempel::Error>(())
+/// ```
+#[cfg(feature = "external")]
+pub type OwnedStemmer<C = CommandSlice> = Stemmer<C, Owned>;
+
+/// A stemming table in the flat layout used by [`Stemmer`], which other formats can share the lookup logic with.
+pub(crate) trait FlatTable {
+    type Commands: PackedCommands;
+
+    fn num_tries(&self) -> usize;
+    /// Returns the last commands found on the path of `word` through the trie, reading the word from its end, passing
+    /// each cell on the path to `trace`.
+    fn lookup(
+        &self,
+        trie: usize,
+        word: &str,
+        trace: &mut impl Trace<Self::Commands>,
+    ) -> Option<Self::Commands>;
+    fn command(&self, index: usize) -> Command;
+}
+
+impl<C: PackedCommands, S: Storage> FlatTable for Stemmer<C, S> {
+    type Commands = C;
+
+    fn num_tries(&self) -> usize {
+        self.tries.len()
+    }
+
+    fn lookup(&self, trie: usize, word: &str, trace: &mut impl Trace<C>) -> Option<C> {
+        self.tries[trie].get(word, trace)
+    }
+
+    fn command(&self, index: usize) -> Command {
+        self.commands[index]
     }
 }
 
-fn apply_edits(mut result: Vec<char>, cmds: &[Command]) -> Option<String> {
-    let mut pos = (result.len() - 1) as isize;
-    for &command in cmds {
-        match command {
-            Command::Skip { chars } => pos -= chars as isize,
-            Command::Delete { chars } => {
-                let e = usize::try_from(pos).ok()?;
-                pos -= chars as isize;
-                let s = usize::try_from(pos).ok()?;
-                result.drain(s..=e);
-            }
-            Command::Replace { char } => *result.get_mut(usize::try_from(pos).ok()?)? = char,
-            Command::Insert { char } => {
-                pos += 1;
-                result.insert(usize::try_from(pos).ok()?, char);
-            }
-        }
-        pos -= 1;
+impl<C: PackedCommands, S: Storage> Stemmer<C, S> {
+    /// Stems `word` like [`Stem::stem`], returning a trace of the tries consulted and the commands applied.
+    pub fn stem_explain(&self, word: &str) -> Explanation {
+        explain(self, word)
+    }
+
+    /// Returns the commands which [`Stem::stem`] applies to `word`, or `None` if it leaves the word unchanged without
+    /// looking up any. See [`apply`].
+    pub fn command_for(&self, word: &str) -> Option<Vec<Command>> {
+        command_for(self, word)
     }
-    if result.is_empty() {
-        None
+}
+
+impl<C: PackedCommands, S: Storage> Stem for Stemmer<C, S> {
+    fn stem<'a>(&self, word: &'a str) -> Cow<'a, str> {
+        stem(self, word)
+    }
+
+    fn stem_into(&self, word: &str, out: &mut String) -> bool {
+        stem_into(self, word, out, &StemmerOptions::new())
+    }
+
+    fn stem_into_with(&self, word: &str, out: &mut String, options: &StemmerOptions) -> bool {
+        stem_into(self, word, out, options)
+    }
+}
+
+pub(crate) fn stem<'a>(table: &impl FlatTable, word: &'a str) -> Cow<'a, str> {
+    let mut out = String::new();
+    if edit(table, word, &mut out, &StemmerOptions::new()) {
+        Cow::Owned(out)
     } else {
-        Some(result.into_iter().collect())
+        Cow::Borrowed(word)
     }
 }
 
-fn skip(key: &mut &[char], cmds: &[Command]) -> bool {
-    let cnt: usize = cmds.iter().map(|c| c.length_pp()).sum();
-    if cnt == 0 {
+pub(crate) fn stem_into(
+    table: &impl FlatTable,
+    word: &str,
+    out: &mut String,
+    options: &StemmerOptions,
+) -> bool {
+    if edit(table, word, out, options) {
         return true;
     }
-    if cnt > key.len() {
+    out.clear();
+    out.push_str(word);
+    false
+}
+
+/// Writes the stem of `word` to `out` and returns `true` if it differs from `word`. Otherwise returns `false`, and
+/// `out` is only written to if the word had to be normalized, so that words which the table leaves unchanged don't
+/// need a buffer.
+fn edit(table: &impl FlatTable, word: &str, out: &mut String, options: &StemmerOptions) -> bool {
+    if let Cow::Owned(normalized) = normalize::nfc(word) {
+        if !edit(table, &normalized, out, options) {
+            out.clear();
+            out.push_str(&normalized);
+        }
+        return out != word;
+    }
+    if options.is_too_short(word) {
         return false;
     }
-    let end = key.len() - cnt;
-    *key = &key[..end];
+    let mut editor = Editor::lazy(word, out);
+    let mut applied = true;
+    get_cmd(
+        table,
+        word,
+        options.max_stages,
+        |cmd| {
+            applied = applied && editor.apply(cmd).is_some();
+        },
+        &mut (),
+    );
+    let edited = editor.is_edited();
+    applied && edited && editor.finish().is_some() && !options.is_stem_too_short(out) && out != word
+}
+
+pub(crate) fn explain<T: FlatTable>(table: &T, word: &str) -> Explanation {
+    let word = &*normalize::nfc(word);
+    let mut tracer = Tracer::new(|cs| commands(table, cs).collect());
+    let options = StemmerOptions::new();
+    if !options.is_too_short(word) {
+        get_cmd(table, word, options.max_stages, |_| (), &mut tracer);
+    }
+    tracer.finish(word)
+}
+
+pub(crate) fn command_for(table: &impl FlatTable, word: &str) -> Option<Vec<Command>> {
+    let word = normalize::nfc(word);
+    let options = StemmerOptions::new();
+    if options.is_too_short(&word) {
+        return None;
+    }
+    let mut commands = Vec::new();
+    get_cmd(
+        table,
+        &word,
+        options.max_stages,
+        |cmd| commands.push(cmd),
+        &mut (),
+    );
+    Some(commands).filter(|commands| !commands.is_empty())
+}
+
+/// Applies commands returned by `command_for` to `word`, as [`Stem::stem`] does. Returns `None` if they can't be
+/// applied, or would remove every char, in which case `stem` leaves the word unchanged.
+pub fn apply(word: &str, commands: &[Command]) -> Option<String> {
+    let mut out = normalize::nfc(word).into_owned();
+    let mut editor = Editor::new(&mut out);
+    for &command in commands {
+        editor.apply(command)?;
+    }
+    editor.finish()?;
+    Some(out)
+}
+
+/// Removes `cnt` chars from the end of the key, returning false if it's shorter.
+fn skip(key: &mut &str, cnt: usize) -> bool {
+    let mut chars = key.chars();
+    for _ in 0..cnt {
+        if chars.next_back().is_none() {
+            return false;
+        }
+    }
+    *key = chars.as_str();
     true
 }
 
-impl Stemmer {
-    fn get_cmd(&self, mut key: &[char]) -> Option<Vec<Command>> {
-        let mut result = Vec::new();
-        let mut last_key = key;
-        let mut prev_cmds = None;
-        let mut last_cmd = None;
-        for trie in self.tries {
-            let cmd = match trie.get(last_key) {
-                Some(cs) if cs.is_eom() => break,
-                Some(cs) => cs.lookup(self.commands),
-                None => break,
-            };
-            if let Some(lc) = last_cmd {
-                if cmd[0].cannot_follow(lc) {
-                    break;
-                }
+fn commands<T: FlatTable>(table: &T, cs: T::Commands) -> impl Iterator<Item = Command> + '_ {
+    let start = cs.start_index();
+    (start..start + cs.len()).map(move |idx| table.command(idx))
+}
+
+fn length_pp<T: FlatTable>(table: &T, cs: T::Commands) -> usize {
+    commands(table, cs).map(|c| c.length_pp()).sum()
+}
+
+/// Passes each command for `key` from the first `max_stages` tries to `visit`, in order, and each trie consulted to
+/// `trace`.
+fn get_cmd<T: FlatTable>(
+    table: &T,
+    mut key: &str,
+    max_stages: usize,
+    mut visit: impl FnMut(Command),
+    trace: &mut impl Trace<T::Commands>,
+) {
+    let mut last_key = key;
+    let mut prev_cmds = None;
+    let mut last_cmd = None;
+    for trie in 0..table.num_tries().min(max_stages) {
+        let cmd = match table.lookup(trie, last_key, trace) {
+            Some(cs) if cs.is_eom() => {
+                trace.stage(trie, last_key, None, Decision::EndOfCommand);
+                break;
             }
-            last_cmd = cmd.last().cloned();
-            if cmd[0].is_skip() {
-                if let Some(prev_cmds) = prev_cmds {
-                    if !skip(&mut key, prev_cmds) {
-                        break;
-                    }
-                }
-                if !skip(&mut key, cmd) {
-                    break;
-                }
+            Some(cs) => cs,
+            None => {
+                trace.stage(trie, last_key, None, Decision::NotFound);
+                break;
+            }
+        };
+        let first = table.command(cmd.start_index());
+        if let Some(lc) = last_cmd {
+            if first.cannot_follow(lc) {
+                trace.stage(trie, last_key, Some(cmd), Decision::CannotFollow);
+                break;
             }
-            prev_cmds = Some(cmd);
-            result.extend_from_slice(cmd);
-            if !key.is_empty() {
-                last_key = key;
+        }
+        last_cmd = Some(table.command(cmd.start_index() + cmd.len() - 1));
+        if first.is_skip() {
+            let fits = match prev_cmds {
+                Some(prev_cmds) => skip(&mut key, length_pp(table, prev_cmds)),
+                None => true,
+            };
+            if !fits || !skip(&mut key, length_pp(table, cmd)) {
+                trace.stage(trie, last_key, Some(cmd), Decision::KeyTooShort);
+                break;
             }
         }
-        Some(result)
+        trace.stage(trie, last_key, Some(cmd), Decision::Used);
+        prev_cmds = Some(cmd);
+        commands(table, cmd).for_each(&mut visit);
+        if !key.is_empty() {
+            last_key = key;
+        }
     }
 }
 
@@ -292,16 +521,125 @@ impl Stemmer {
 mod test {
     use super::*;
     use crate::Stem;
-    use flate2::bufread::GzDecoder;
-    use std::fs;
-    use std::io::{prelude::*, BufReader};
+
+    #[test]
+    fn test_wide_command_slice() {
+        let cs = WideCommandSlice::new(1 << 40, 300);
+        assert_eq!((cs.start_index(), cs.len()), (1 << 40, 300));
+        assert!(WideCommandSlice::new_eom().is_eom());
+        assert!(CommandSlice::try_new(0, 16).is_none());
+        assert!(CommandSlice::try_new(1 << 24, 1).is_none());
+
+        // A single row which inserts 20 chars after words ending with 'a'.
+        let commands = vec![Command::Insert { char: 'x' }; 20].leak();
+        let cells = vec![Cell {
+            refr: None,
+            cmds: Some(WideCommandSlice::new(0, commands.len())),
+        }]
+        .leak();
+        let rows = vec![Row::<WideCommandSlice> {
+            cells,
+            chars: &['a'],
+        }]
+        .leak();
+        let stemmer = Stemmer::<WideCommandSlice> {
+            commands,
+            tries: vec![Trie::<WideCommandSlice> { rows }].leak(),
+        };
+        assert_eq!(stemmer.stem("kota"), format!("kota{}", "x".repeat(20)));
+        assert_eq!(stemmer.stem("kotu"), "kotu");
+    }
+
+    #[cfg(feature = "table_2000")]
+    #[test]
+    fn test_stem_into() {
+        let mut buffer = crate::StemBuffer::new();
+        let mut out = String::new();
+        for word in ["kotami", "książkami", "czytałem", "kot", "Abadan", "zzzz"] {
+            let stem = STEMMER.stem(word);
+            assert_eq!(STEMMER.stem_into(word, &mut out), stem != word);
+            assert_eq!(out, stem);
+            assert_eq!(buffer.stem(&STEMMER, word), stem);
+        }
+    }
+
+    #[cfg(feature = "table_2000")]
+    #[test]
+    fn test_stem_explain() {
+        for word in [
+            "kotami",
+            "książkami",
+            "czytałem",
+            "robiliśmy",
+            "kot",
+            "zzzz",
+        ] {
+            let explanation = STEMMER.stem_explain(word);
+            assert_eq!(explanation.word, word);
+            assert_eq!(explanation.stem, STEMMER.stem(word));
+            if let Some(last) = explanation.edits.last() {
+                assert_eq!(last.word.as_deref(), Some(&*explanation.stem));
+            }
+            if let Some(first) = explanation.stages.first() {
+                assert_eq!(first.key, word);
+            }
+            for (idx, stage) in explanation.stages.iter().enumerate() {
+                assert_eq!(stage.trie, idx);
+                if stage.decision == Decision::Used {
+                    assert!(stage.path[stage.cell.unwrap()].has_command);
+                    assert!(!stage.commands.is_empty());
+                } else {
+                    assert_eq!(idx, explanation.stages.len() - 1);
+                }
+            }
+        }
+
+        let explanation = STEMMER.stem_explain("kot");
+        assert!(explanation.stages.is_empty() && explanation.edits.is_empty());
+        assert_eq!(explanation.stem, "kot");
+
+        let explanation = STEMMER.stem_explain("kotami");
+        assert_eq!(explanation.stages[0].path[0].ch, 'i');
+        let applied: Vec<_> = explanation.edits.iter().map(|edit| edit.command).collect();
+        let used: Vec<_> = explanation
+            .stages
+            .iter()
+            .filter(|stage| stage.decision == Decision::Used)
+            .flat_map(|stage| stage.commands.clone())
+            .collect();
+        assert_eq!(applied, used);
+    }
+
+    #[cfg(feature = "table_2000")]
+    #[test]
+    fn test_command_for() {
+        for word in ["kotami", "czytałem", "robiliśmy", "ludziom"] {
+            let commands = STEMMER.command_for(word).expect(word);
+            assert_eq!(apply(word, &commands).unwrap(), STEMMER.stem(word));
+            assert_eq!(
+                commands,
+                STEMMER
+                    .stem_explain(word)
+                    .edits
+                    .iter()
+                    .map(|edit| edit.command)
+                    .collect::<Vec<_>>()
+            );
+        }
+        assert_eq!(STEMMER.command_for("kot"), None);
+
+        let commands = [Command::Delete { chars: 1 }, Command::Insert { char: 'a' }];
+        assert_eq!(apply("kotami", &commands).unwrap(), "kotaa");
+        assert_eq!(apply("ab", &[Command::Delete { chars: 1 }]), None);
+        assert_eq!(apply("ab", &[Command::Delete { chars: 2 }]), None);
+    }
 
     #[cfg(feature = "table_2000")]
     #[test]
     fn test_compare_stem_to_stempel() {
-        let path = "src/tables/polimorf_words_stemmed.tab.gz";
-        let file = fs::File::open(path).unwrap();
-        let mut reader = BufReader::new(GzDecoder::new(BufReader::new(file)));
+        use std::io::prelude::*;
+
+        let mut reader = crate::test_util::open_gz("src/tables/polimorf_words_stemmed.tab.gz");
         let mut line = String::new();
 
         let stemmer: &Stemmer = &STEMMER;
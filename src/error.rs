@@ -0,0 +1,193 @@
+use std::{fmt, io};
+
+/// An error loading or converting a stemming table, or loading overrides.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// Reading the table failed.
+    Io(io::Error),
+    /// The table ended at byte `offset`, before it was complete.
+    Truncated { offset: u64 },
+    /// The string at byte `offset` isn't valid modified UTF-8.
+    InvalidString { offset: u64 },
+    /// The char at byte `offset` is an unpaired UTF-16 surrogate.
+    InvalidChar { offset: u64 },
+    /// The count or index at byte `offset` is negative.
+    NegativeValue { offset: u64 },
+    /// The root of trie `trie` is a row which doesn't exist.
+    InvalidRoot { trie: usize, root: u32 },
+    /// The cell for `cell` in row `row` of trie `trie` refers to a row which doesn't exist.
+    InvalidRowReference { trie: usize, row: usize, cell: char },
+    /// The cell for `cell` in row `row` of trie `trie` refers to a command which doesn't exist.
+    InvalidCommandIndex { trie: usize, row: usize, cell: char },
+    /// The command string with index `index` in trie `trie`, read at byte `offset`, contains an unknown opcode. `cell`
+    /// is the row and char of the first cell which refers to it, if any.
+    UnknownOpcode {
+        trie: usize,
+        index: usize,
+        offset: u64,
+        cell: Option<(usize, char)>,
+        command: String,
+        opcode: char,
+    },
+    /// The command string with index `index` in trie `trie`, read at byte `offset`, contains a parameter which is out
+    /// of range for its opcode, or is missing one. `cell` is the row and char of the first cell which refers to it, if
+    /// any.
+    InvalidParameter {
+        trie: usize,
+        index: usize,
+        offset: u64,
+        cell: Option<(usize, char)>,
+        command: String,
+        param: Option<char>,
+    },
+    /// The table was compiled with a method string which isn't supported here.
+    UnsupportedMethod(String),
+    /// The table has more commands, or longer lists of them, than its command slice type can refer to.
+    TooManyCommands,
+    /// The table doesn't start with the magic bytes of the binary format.
+    InvalidMagic,
+    /// The table is in a version of the binary format which isn't supported here.
+    UnsupportedVersion(u32),
+    /// The table continues past byte `offset`, where its header says it ends.
+    TrailingData { offset: u64 },
+    /// The command at byte `offset` has an unknown kind, or a parameter which is out of range for it.
+    InvalidCommand { offset: u64 },
+    /// The cells of row `row` in trie `trie` are out of range, or not sorted by their chars.
+    InvalidRow { trie: usize, row: usize },
+    /// Line `line` of an overrides file isn't a word, optionally followed by a tab and its stem.
+    InvalidOverride { line: usize },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "failed to read stemming table: {}", err),
+            Error::Truncated { offset } => {
+                write!(f, "stemming table is truncated at byte {}", offset)
+            }
+            Error::InvalidString { offset } => {
+                write!(f, "invalid modified UTF-8 string at byte {}", offset)
+            }
+            Error::InvalidChar { offset } => write!(f, "invalid UTF-16 char at byte {}", offset),
+            Error::NegativeValue { offset } => write!(f, "negative value at byte {}", offset),
+            Error::InvalidRoot { trie, root } => {
+                write!(f, "root {} of trie {} doesn't exist", root, trie)
+            }
+            Error::InvalidRowReference { trie, row, cell } => write!(
+                f,
+                "cell {:?} of row {} in trie {} refers to a row which doesn't exist",
+                cell, row, trie
+            ),
+            Error::InvalidCommandIndex { trie, row, cell } => write!(
+                f,
+                "cell {:?} of row {} in trie {} refers to a command which doesn't exist",
+                cell, row, trie
+            ),
+            Error::UnknownOpcode {
+                trie,
+                index,
+                offset,
+                cell,
+                command,
+                opcode,
+            } => write!(
+                f,
+                "unknown opcode {:?} in command {} {:?} of trie {} at byte {}{}",
+                opcode,
+                index,
+                command,
+                trie,
+                offset,
+                UsedBy(*cell)
+            ),
+            Error::InvalidParameter {
+                trie,
+                index,
+                offset,
+                cell,
+                command,
+                param: Some(param),
+            } => write!(
+                f,
+                "parameter {:?} is out of range in command {} {:?} of trie {} at byte {}{}",
+                param,
+                index,
+                command,
+                trie,
+                offset,
+                UsedBy(*cell)
+            ),
+            Error::InvalidParameter {
+                trie,
+                index,
+                offset,
+                cell,
+                command,
+                param: None,
+            } => write!(
+                f,
+                "missing parameter in command {} {:?} of trie {} at byte {}{}",
+                index,
+                command,
+                trie,
+                offset,
+                UsedBy(*cell)
+            ),
+            Error::UnsupportedMethod(method) => {
+                write!(f, "unsupported stemming table method {:?}", method)
+            }
+            Error::TooManyCommands => {
+                write!(
+                    f,
+                    "stemming table has too many commands for its command slice type"
+                )
+            }
+            Error::InvalidMagic => write!(f, "not a binary stemming table"),
+            Error::UnsupportedVersion(version) => {
+                write!(f, "unsupported binary stemming table version {}", version)
+            }
+            Error::TrailingData { offset } => {
+                write!(
+                    f,
+                    "unexpected data after the stemming table at byte {}",
+                    offset
+                )
+            }
+            Error::InvalidCommand { offset } => write!(f, "invalid command at byte {}", offset),
+            Error::InvalidRow { trie, row } => write!(
+                f,
+                "cells of row {} in trie {} are out of range or unsorted",
+                row, trie
+            ),
+            Error::InvalidOverride { line } => write!(f, "invalid override on line {}", line),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+/// Describes the cell which refers to an invalid command, if it's known.
+struct UsedBy(Option<(usize, char)>);
+
+impl fmt::Display for UsedBy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some((row, cell)) => write!(f, ", used by cell {:?} of row {}", cell, row),
+            None => Ok(()),
+        }
+    }
+}
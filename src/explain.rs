@@ -0,0 +1,151 @@
+//! Traces how a word is stemmed, for debugging stemming tables. See `stem_explain` on each stemmer.
+
+use crate::{edit::Editor, Command};
+
+/// What the stemmer did with the command it looked up in a trie.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Decision {
+    /// The commands were used.
+    Used,
+    /// The trie has no command for the key, so stemming stopped.
+    NotFound,
+    /// The trie marks the end of the word's commands.
+    EndOfCommand,
+    /// The first command can't follow the last command used, e.g. a skip after a skip, so stemming stopped.
+    CannotFollow,
+    /// The key is shorter than the chars skipped by the commands, so stemming stopped.
+    KeyTooShort,
+    /// The command is malformed, so stemming stopped.
+    InvalidCommand,
+}
+
+/// A cell on the path of a key through a trie.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PathStep {
+    /// The index of the row within its trie.
+    pub row: usize,
+    /// The char of the key which selected the cell.
+    pub ch: char,
+    /// True if the cell has a command. The last of these on the path supplies the command for the key.
+    pub has_command: bool,
+}
+
+/// Receives each step of a lookup. Stemming without a trace uses `()`, which ignores them.
+pub(crate) trait Trace<C> {
+    fn step(&mut self, _row: usize, _ch: char, _has_command: bool) {}
+
+    /// Called once per trie consulted, with the key looked up and the commands found, if any.
+    fn stage(&mut self, _trie: usize, _key: &str, _commands: Option<C>, _decision: Decision) {}
+}
+
+impl<C> Trace<C> for () {}
+
+/// A trie consulted while stemming a word.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stage {
+    /// The index of the trie.
+    pub trie: usize,
+    /// The key looked up in the trie, which is the word with the chars skipped by earlier stages removed.
+    pub key: String,
+    /// The cells visited while looking up the key.
+    pub path: Vec<PathStep>,
+    /// The index in `path` of the cell which supplied the commands.
+    pub cell: Option<usize>,
+    /// The commands found in the trie, whether or not they were used.
+    pub commands: Vec<Command>,
+    pub decision: Decision,
+}
+
+/// A command applied to the word.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+    pub command: Command,
+    /// The word after the command, or `None` if it couldn't be applied and the word was left unchanged.
+    pub word: Option<String>,
+}
+
+/// A trace of how a word was stemmed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Explanation {
+    pub word: String,
+    /// Each trie consulted, in order. Words of 3 or fewer chars are left unchanged without consulting any.
+    pub stages: Vec<Stage>,
+    /// The commands of every stage which was used, in order.
+    pub edits: Vec<Edit>,
+    /// The stemmed word, as returned by `stem`.
+    pub stem: String,
+}
+
+/// Builds an [`Explanation`] from the trace of a lookup, using `decode` to convert the commands of each stage.
+pub(crate) struct Tracer<F> {
+    decode: F,
+    path: Vec<PathStep>,
+    stages: Vec<Stage>,
+}
+
+impl<C, F: FnMut(C) -> Vec<Command>> Trace<C> for Tracer<F> {
+    fn step(&mut self, row: usize, ch: char, has_command: bool) {
+        self.path.push(PathStep {
+            row,
+            ch,
+            has_command,
+        });
+    }
+
+    fn stage(&mut self, trie: usize, key: &str, commands: Option<C>, decision: Decision) {
+        let path = std::mem::take(&mut self.path);
+        self.stages.push(Stage {
+            trie,
+            key: key.into(),
+            cell: path.iter().rposition(|step| step.has_command),
+            path,
+            commands: commands.map_or_else(Vec::new, &mut self.decode),
+            decision,
+        });
+    }
+}
+
+impl<F> Tracer<F> {
+    pub fn new(decode: F) -> Self {
+        Self {
+            decode,
+            path: Vec::new(),
+            stages: Vec::new(),
+        }
+    }
+
+    /// Applies the commands of the stages which were used to `word`.
+    pub fn finish(self, word: &str) -> Explanation {
+        let mut edits = Vec::new();
+        let mut result = String::from(word);
+        let mut editor = Editor::new(&mut result);
+        let commands = self
+            .stages
+            .iter()
+            .filter(|stage| stage.decision == Decision::Used)
+            .flat_map(|stage| &stage.commands);
+        let mut applied = true;
+        for &command in commands {
+            applied = editor.apply(command).is_some();
+            edits.push(Edit {
+                command,
+                word: applied.then(|| editor.word().to_owned()),
+            });
+            if !applied {
+                break;
+            }
+        }
+        let stem = if applied && editor.finish().is_some() {
+            result
+        } else {
+            word.into()
+        };
+        Explanation {
+            word: word.into(),
+            stages: self.stages,
+            edits,
+            stem,
+        }
+    }
+}
@@ -1,38 +1,286 @@
+//! Patch commands which transform a word into its stem, as used by the Egothor stemmer.
+//!
+//! A patch is a string of two-char commands applied from the end of the word towards its start:
+//! `-x` skips, `Dx` deletes, `Rc` replaces the current char with `c` and `Ic` inserts `c`. The `x` parameter counts
+//! chars, where `a` is 1, `b` is 2 and so on. See [`Command`] for the parsed form used by the tables.
+
+use crate::edit::Editor;
+use crate::Command;
+
+/// Applies the patch command `diff` to `orig`, returning `None` if the patch is malformed, can't be applied or would
+/// remove every char.
 pub fn apply(orig: &str, diff: &str) -> Option<String> {
     if orig.is_empty() {
         return None;
     }
-    let mut result = Vec::from_iter(orig.chars());
-    let mut pos = (result.len() - 1) as isize;
-    let mut chars = diff.chars();
-    // TODO: replace with next_chunk when stable
-    while let (Some(cmd), Some(param)) = (chars.next(), chars.next()) {
-        match cmd {
-            '-' => {
-                assert!(param.is_ascii_lowercase());
-                let par_num = ((param as u8) - b'a') as isize;
-                pos -= par_num;
+    let commands = Command::parse_patch(diff).ok()?;
+    let mut result = String::from(orig);
+    let mut editor = Editor::new(&mut result);
+    for cmd in commands {
+        editor.apply(cmd)?;
+    }
+    editor.finish()?;
+    Some(result)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Way {
+    Noop,
+    Delete,
+    Insert,
+    Replace,
+}
+
+/// Appends the commands for a run of `count` skipped or deleted chars, if there is one, and resets the run. A command
+/// covers at most 256 chars, so longer runs are split into several commands.
+fn flush(result: &mut Vec<Command>, command: fn(u8) -> Command, count: &mut usize) {
+    while *count > 0 {
+        let chars = (*count).min(usize::from(u8::MAX) + 1);
+        result.push(command((chars - 1) as u8));
+        *count -= chars;
+    }
+}
+
+fn skip(chars: u8) -> Command {
+    Command::Skip { chars }
+}
+
+fn delete(chars: u8) -> Command {
+    Command::Delete { chars }
+}
+
+/// The costs of each edit, used to choose between patches which all produce the same stem.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Diff {
+    pub insert: u32,
+    pub delete: u32,
+    pub replace: u32,
+    /// The cost of keeping a char unchanged.
+    pub noop: u32,
+}
+
+impl Default for Diff {
+    /// The costs used by the Java `Compile` tool.
+    fn default() -> Self {
+        Self {
+            insert: 1,
+            delete: 1,
+            replace: 1,
+            noop: 0,
+        }
+    }
+}
+
+impl Diff {
+    /// Computes the cheapest patch command which [`apply`] uses to transform `word` into `stem`, like Egothor's
+    /// `Diff.exec`.
+    pub fn compute(&self, word: &str, stem: &str) -> String {
+        let mut result = String::new();
+        for cmd in self.compute_commands(word, stem) {
+            cmd.push_to(&mut result);
+        }
+        result
+    }
+
+    /// Like [`Diff::compute`], but returns the patch as parsed [`Command`]s.
+    ///
+    /// Runs of more than 256 skipped or deleted chars, which Egothor encodes in a single command, are split into
+    /// several commands of the same kind.
+    pub fn compute_commands(&self, word: &str, stem: &str) -> Vec<Command> {
+        let a = word.chars().collect::<Vec<_>>();
+        let b = stem.chars().collect::<Vec<_>>();
+        let cols = b.len() + 1;
+        let mut net = vec![0u32; (a.len() + 1) * cols];
+        let mut way = vec![Way::Noop; (a.len() + 1) * cols];
+        for x in 1..=a.len() {
+            net[x * cols] = net[(x - 1) * cols].saturating_add(self.delete);
+            way[x * cols] = Way::Delete;
+        }
+        for y in 1..=b.len() {
+            net[y] = net[y - 1].saturating_add(self.insert);
+            way[y] = Way::Insert;
+        }
+        for x in 1..=a.len() {
+            for y in 1..=b.len() {
+                let diag = net[(x - 1) * cols + y - 1];
+                let delete = net[(x - 1) * cols + y].saturating_add(self.delete);
+                let insert = net[x * cols + y - 1].saturating_add(self.insert);
+                let replace = diag.saturating_add(self.replace);
+                // Ties are broken in the same order as the Java implementation so we produce identical commands.
+                let (mut min_way, mut min) = (Way::Delete, delete);
+                if a[x - 1] == b[y - 1] {
+                    let noop = diag.saturating_add(self.noop);
+                    if noop < delete {
+                        (min_way, min) = (Way::Noop, noop);
+                    }
+                }
+                if min > insert {
+                    (min_way, min) = (Way::Insert, insert);
+                }
+                if min > replace {
+                    (min_way, min) = (Way::Replace, replace);
+                }
+                way[x * cols + y] = min_way;
+                net[x * cols + y] = min;
             }
-            'R' => *result.get_mut(usize::try_from(pos).ok()?)? = param,
-            'D' => {
-                assert!(param.is_ascii_lowercase());
-                let par_num = ((param as u8) - b'a') as isize;
-                let e = usize::try_from(pos).ok()?;
-                pos -= par_num;
-                let s = usize::try_from(pos).ok()?;
-                result.drain(s..=e);
+        }
+
+        let mut result = Vec::new();
+        let mut deletes = 0;
+        let mut equals = 0;
+        let (mut x, mut y) = (a.len(), b.len());
+        while x + y != 0 {
+            match way[x * cols + y] {
+                Way::Delete => {
+                    flush(&mut result, skip, &mut equals);
+                    deletes += 1;
+                    x -= 1;
+                }
+                Way::Insert => {
+                    flush(&mut result, delete, &mut deletes);
+                    flush(&mut result, skip, &mut equals);
+                    y -= 1;
+                    result.push(Command::Insert { char: b[y] });
+                }
+                Way::Replace => {
+                    flush(&mut result, delete, &mut deletes);
+                    flush(&mut result, skip, &mut equals);
+                    x -= 1;
+                    y -= 1;
+                    result.push(Command::Replace { char: b[y] });
+                }
+                Way::Noop => {
+                    flush(&mut result, delete, &mut deletes);
+                    equals += 1;
+                    x -= 1;
+                    y -= 1;
+                }
             }
-            'I' => {
-                pos += 1;
-                result.insert(usize::try_from(pos).ok()?, param);
+        }
+        flush(&mut result, delete, &mut deletes);
+        result
+    }
+}
+
+/// Computes the patch command which [`apply`] uses to transform `word` into `stem`, using the default [`Diff`] costs.
+pub fn compute(word: &str, stem: &str) -> String {
+    Diff::default().compute(word, stem)
+}
+
+/// Like [`compute`], but returns the patch as parsed [`Command`]s. See [`Diff::compute_commands`].
+pub fn compute_commands(word: &str, stem: &str) -> Vec<Command> {
+    Diff::default().compute_commands(word, stem)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const PAIRS: &[(&str, &str)] = &[
+        ("kotami", "kot"),
+        ("psem", "pies"),
+        ("psie", "pies"),
+        ("książkami", "książka"),
+        ("czytałem", "czytać"),
+        ("najlepszy", "dobry"),
+        ("ludzie", "człowiek"),
+        ("abc", ""),
+        ("", "abc"),
+        (
+            "nieprzyzwoitymiabcdefghijklmnopqrstuvwxyz",
+            "przyzwoitymiabcdefghijklmnopqrstuvwxyz",
+        ),
+    ];
+
+    #[test]
+    fn test_compute() {
+        assert_eq!(compute("kotami", "kot"), "Dc");
+        assert_eq!(compute("psem", "pies"), "Rs-aRi");
+        assert_eq!(compute("psie", "pies"), "Is-bDa");
+    }
+
+    #[test]
+    fn test_compute_round_trips() {
+        let costs = [
+            Diff::default(),
+            Diff {
+                insert: 5,
+                delete: 1,
+                replace: 50,
+                noop: 0,
+            },
+            Diff {
+                insert: 1,
+                delete: 1,
+                replace: 1,
+                noop: 200,
+            },
+        ];
+        for diff in costs {
+            for &(word, stem) in PAIRS.iter().filter(|(w, s)| !w.is_empty() && !s.is_empty()) {
+                let patch = diff.compute(word, stem);
+                assert_eq!(
+                    apply(word, &patch).as_deref(),
+                    Some(stem),
+                    "{:?} {}",
+                    diff,
+                    patch
+                );
             }
-            _ => unreachable!(),
         }
-        pos -= 1;
     }
-    if result.is_empty() {
-        None
-    } else {
-        Some(result.into_iter().collect())
+
+    #[test]
+    fn test_apply_malformed() {
+        assert_eq!(apply("kotami", "Xa"), None);
+        assert_eq!(apply("kotami", "D!"), None);
+        assert_eq!(apply("kotami", "-\u{0}"), None);
+        assert_eq!(apply("kotami", "Dz"), None);
+    }
+
+    #[test]
+    fn test_compute_empty() {
+        assert_eq!(compute("abc", ""), "Dc");
+        assert_eq!(apply("abc", "Dc"), None);
+        assert_eq!(compute("", "abc"), "IcIbIa");
+    }
+
+    #[test]
+    fn test_compute_long_runs() {
+        let long = "a".repeat(300);
+        let (word, stem) = (format!("b{}", long), format!("c{}", long));
+        assert_eq!(
+            compute_commands(&word, &stem),
+            [
+                Command::Skip { chars: 255 },
+                Command::Skip { chars: 43 },
+                Command::Replace { char: 'c' },
+            ]
+        );
+        assert_eq!(apply(&word, &compute(&word, &stem)).unwrap(), stem);
+
+        let word = format!("kot{}", "x".repeat(600));
+        assert_eq!(
+            compute_commands(&word, "kot"),
+            [
+                Command::Delete { chars: 255 },
+                Command::Delete { chars: 255 },
+                Command::Delete { chars: 87 },
+            ]
+        );
+        assert_eq!(apply(&word, &compute(&word, "kot")).unwrap(), "kot");
+    }
+
+    #[test]
+    fn test_compute_commands_round_trips() {
+        for &(word, stem) in PAIRS.iter().filter(|(w, s)| !w.is_empty() && !s.is_empty()) {
+            let mut result = String::from(word);
+            let mut editor = Editor::new(&mut result);
+            for cmd in compute_commands(word, stem) {
+                editor.apply(cmd).unwrap();
+            }
+            editor.finish().unwrap();
+            assert_eq!(result, stem);
+        }
     }
 }
@@ -1,63 +1,664 @@
+use crate::edit::Editor;
+use crate::explain::{Decision, Explanation, Trace, Tracer};
+use crate::normalize;
+use crate::{Command, Error, StemmerOptions};
 use multitrie::MultiTrie2;
-use serialize::{DataInput, JavaDeserialize};
+use serialize::{DataInput, DataOutput, JavaDeserialize, JavaSerialize};
 use std::{borrow::Cow, io};
-use trie::{Trie, TrieGet};
+use trie::{Patch, Trie, TrieGet};
 
-pub(crate) mod diff;
-pub(crate) mod multitrie;
+pub mod diff;
+pub mod multitrie;
+pub mod reduce;
+mod reload;
 pub(crate) mod serialize;
-pub(crate) mod trie;
+pub mod trie;
 
+pub use reload::ReloadableStemmer;
+
+/// A stemming table, either a single [`Trie`] holding whole commands or a [`MultiTrie2`].
+#[derive(Debug, Clone)]
+pub enum Table {
+    Trie(Trie),
+    MultiTrie2(MultiTrie2),
+}
+
+impl Table {
+    /// Stores the commands `cmd` for `key`.
+    pub fn add(&mut self, key: &str, cmd: &[Command]) {
+        match self {
+            Table::Trie(trie) => trie.add(key, cmd),
+            Table::MultiTrie2(trie) => trie.add(key, cmd),
+        }
+    }
+
+    /// Stores the commands `cmd` for `key`, only changing the commands for other keys which contain `key`. See
+    /// [`Trie::patch`].
+    pub fn patch(&mut self, key: &str, cmd: &[Command]) {
+        match self {
+            // An empty command can't be stored in a single trie, so use a skip which leaves the word unchanged.
+            Table::Trie(trie) if cmd.is_empty() => {
+                trie.patch(key, vec![Command::Skip { chars: 0 }])
+            }
+            Table::Trie(trie) => trie.patch(key, cmd),
+            Table::MultiTrie2(trie) => trie.patch(key, cmd),
+        }
+    }
+
+    /// Checks that the table can be used without panicking, see [`Trie::validate`].
+    pub fn validate(&self) -> Result<(), Error> {
+        match self {
+            Table::Trie(trie) => trie.validate(),
+            Table::MultiTrie2(trie) => trie.validate(),
+        }
+    }
+}
+
+impl JavaSerialize for Table {
+    fn serialize<W: io::Write>(&self, writer: &mut DataOutput<W>) -> io::Result<()> {
+        match self {
+            Table::Trie(trie) => writer.write(trie),
+            Table::MultiTrie2(trie) => writer.write(trie),
+        }
+    }
+}
+
+impl TrieGet for Table {
+    fn get_cmd(&self, key: &str) -> Option<String> {
+        match self {
+            Table::Trie(trie) => trie.get_cmd(key),
+            Table::MultiTrie2(trie) => trie.get_cmd(key),
+        }
+    }
+}
+
+/// A stemmer which uses a table in the format used by the Java `stempel` implementation. It's `Send + Sync`, so a
+/// loaded table can be shared between threads, e.g. in an `Arc`.
 pub struct Stemmer {
-    trie: Box<dyn TrieGet>,
+    /// The method string the table was compiled with, e.g. `-ME2`.
+    method: String,
+    table: Table,
 }
 
 impl Stemmer {
-    pub fn load<R: io::Read>(reader: R) -> io::Result<Self> {
+    /// Reads a table in the format used by the Java `stempel` implementation, checking it with [`Table::validate`].
+    pub fn load<R: io::Read>(reader: R) -> Result<Self, Error> {
         let mut reader = DataInput::new(reader);
         let method = reader.read_string()?;
         let multi = method.contains(['M', 'm']);
-        let trie: Box<dyn TrieGet> = if multi {
-            Box::new(MultiTrie2::deserialize(&mut reader)?)
+        let table = if multi {
+            Table::MultiTrie2(MultiTrie2::deserialize(&mut reader)?)
         } else {
-            Box::new(Trie::deserialize(&mut reader)?)
+            Table::Trie(Trie::deserialize(&mut reader)?)
         };
-        Ok(Self { trie })
+        table.validate()?;
+        Ok(Self { method, table })
+    }
+
+    /// Writes the table in the format used by the Java `stempel` implementation, which can be read by [`Stemmer::load`]
+    /// or Lucene's `StempelStemmer`.
+    pub fn save<W: io::Write>(&self, writer: W) -> io::Result<()> {
+        let mut writer = DataOutput::new(writer);
+        writer.write_string(&self.method)?;
+        writer.write(&self.table)
+    }
+
+    /// Creates a stemmer from a table compiled with the given method string.
+    pub fn new(method: impl Into<String>, table: Table) -> Self {
+        Self {
+            method: method.into(),
+            table,
+        }
+    }
+
+    pub fn method(&self) -> &str {
+        &self.method
+    }
+
+    pub fn table(&self) -> &Table {
+        &self.table
+    }
+
+    /// Adds a word and its stem to the table, so that `word` stems to `stem` afterwards. Other words are only affected
+    /// if they end with `word` (or start with it, for forward tables), except in the rare cases described in
+    /// [`MultiTrie2::patch`]. As with any word, `word` is left unchanged if it's 3 or fewer chars long, and `stem`
+    /// must not be empty.
+    pub fn add_pair(&mut self, word: &str, stem: &str) {
+        self.table.patch(word, &diff::compute_commands(word, stem));
+    }
+
+    /// Stems `word` like [`Stem::stem`](crate::Stem::stem), returning a trace of the tries consulted and the commands
+    /// applied.
+    pub fn stem_explain(&self, word: &str) -> Explanation {
+        let word = &*normalize::nfc(word);
+        let mut tracer = Tracer::new(<[Command]>::to_vec);
+        let options = StemmerOptions::new();
+        if !options.is_too_short(word) {
+            match &self.table {
+                Table::Trie(trie) => {
+                    let (cmd, decision) = match trie.get_cmd_traced(word, &mut tracer) {
+                        Some(Patch::Commands(cmd)) => (Some(&**cmd), Decision::Used),
+                        Some(Patch::End) => (None, Decision::EndOfCommand),
+                        None => (None, Decision::NotFound),
+                    };
+                    tracer.stage(0, word, cmd, decision);
+                }
+                Table::MultiTrie2(trie) => {
+                    trie.trace_cmd(word, options.max_stages, |_| (), &mut tracer)
+                }
+            }
+        }
+        tracer.finish(word)
+    }
+
+    /// Returns the commands which [`Stem::stem`](crate::Stem::stem) applies to `word`, or `None` if it leaves the
+    /// word unchanged without looking up any. See [`crate::embedded::apply`].
+    pub fn command_for(&self, word: &str) -> Option<Vec<Command>> {
+        let word = normalize::nfc(word);
+        if StemmerOptions::new().is_too_short(&word) {
+            return None;
+        }
+        let mut commands = Vec::new();
+        match &self.table {
+            Table::Trie(trie) => {
+                if let Some(patch) = trie.get_cmd_ref(&word) {
+                    commands.extend_from_slice(patch.commands());
+                }
+            }
+            Table::MultiTrie2(trie) => trie.visit_cmd(&word, |cmd| commands.extend_from_slice(cmd)),
+        }
+        Some(commands).filter(|commands| !commands.is_empty())
     }
 }
 
-impl crate::Stem for Stemmer {
-    fn stem<'a>(&self, word: &'a str) -> Cow<'a, str> {
+impl Stemmer {
+    /// Writes the stem of `word` to `out` and returns `true` if it differs from `word`. Otherwise returns `false`, and
+    /// `out` is only written to if the word had to be normalized, so that words which the table leaves unchanged don't
+    /// need a buffer.
+    fn edit(&self, word: &str, out: &mut String, options: &StemmerOptions) -> bool {
+        if let Cow::Owned(normalized) = normalize::nfc(word) {
+            if !self.edit(&normalized, out, options) {
+                out.clear();
+                out.push_str(&normalized);
+            }
+            return out != word;
+        }
         // Technically this should be grapheme clusters but the java version assumes that a UTF-16 char is a single char
         // so this should work everywhere that does.
-        if word.chars().count() <= 3 {
-            return Cow::Borrowed(word); // No change
+        if options.is_too_short(word) {
+            return false; // No change
         }
-        let cmd = match self.trie.get_cmd(word) {
-            Some(c) => c,
-            None => return Cow::Borrowed(word),
+        let mut editor = Editor::lazy(word, out);
+        let applied = match &self.table {
+            Table::Trie(_) if options.max_stages == 0 => true,
+            Table::Trie(trie) => match trie.get_cmd_ref(word) {
+                Some(Patch::Commands(cmd)) => cmd.iter().all(|&cmd| editor.apply(cmd).is_some()),
+                Some(Patch::End) | None => false,
+            },
+            Table::MultiTrie2(trie) => {
+                let mut applied = true;
+                trie.trace_cmd(
+                    word,
+                    options.max_stages,
+                    |cmd| {
+                        applied = applied && cmd.iter().all(|&cmd| editor.apply(cmd).is_some());
+                    },
+                    &mut (),
+                );
+                applied
+            }
         };
-        diff::apply(word, &cmd).map_or(Cow::Borrowed(word), Cow::from)
+        let edited = editor.is_edited();
+        applied
+            && edited
+            && editor.finish().is_some()
+            && !options.is_stem_too_short(out)
+            && out != word
+    }
+}
+
+impl crate::Stem for Stemmer {
+    fn stem<'a>(&self, word: &'a str) -> Cow<'a, str> {
+        let mut out = String::new();
+        if self.edit(word, &mut out, &StemmerOptions::new()) {
+            Cow::Owned(out)
+        } else {
+            Cow::Borrowed(word)
+        }
+    }
+
+    fn stem_into(&self, word: &str, out: &mut String) -> bool {
+        self.stem_into_with(word, out, &StemmerOptions::new())
+    }
+
+    fn stem_into_with(&self, word: &str, out: &mut String, options: &StemmerOptions) -> bool {
+        if self.edit(word, out, options) {
+            return true;
+        }
+        out.clear();
+        out.push_str(word);
+        false
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::test_util::{load_stemmer, open_gz, sample_words, STEMMER_2000};
     use crate::Stem;
-    use flate2::bufread::GzDecoder;
-    use std::fs;
     use std::io::prelude::*;
 
+    #[test]
+    fn test_save_round_trip() {
+        let mut orig = Vec::new();
+        open_gz(STEMMER_2000).read_to_end(&mut orig).unwrap();
+        let stemmer = Stemmer::load(orig.as_slice()).unwrap();
+        let mut saved = Vec::new();
+        stemmer.save(&mut saved).unwrap();
+        assert!(orig == saved);
+    }
+
+    #[test]
+    fn test_save_patched() {
+        let mut stemmer = load_stemmer();
+        stemmer.add_pair("ludzie", "człowiek");
+        let mut saved = Vec::new();
+        stemmer.save(&mut saved).unwrap();
+        let stemmer = Stemmer::load(saved.as_slice()).unwrap();
+        assert_eq!(stemmer.stem("ludzie"), "człowiek");
+    }
+
+    #[test]
+    fn test_add_pair() {
+        let mut stemmer = load_stemmer();
+        let others = [
+            "kotami",
+            "książkami",
+            "czytałem",
+            "ludziom",
+            "mieszkanie",
+            "warszawskiego",
+            "prezydenta",
+            "dzieciom",
+            "samochodów",
+            "polskiego",
+            "zdzie",
+            "robiliśmy",
+            "oczach",
+            "siostry",
+            "pięknymi",
+            "ludzieee",
+        ];
+        let before = others.map(|w| stemmer.stem(w).into_owned());
+
+        let pairs = [
+            ("ludzie", "człowiek"),
+            ("allegro", "allegro"),
+            ("sióstr", "siostr"),
+            ("Wrocławia", "Wrocław"),
+        ];
+        for (word, stem) in pairs {
+            stemmer.add_pair(word, stem);
+        }
+        for (word, stem) in pairs {
+            assert_eq!(stemmer.stem(word), stem);
+        }
+        assert_eq!(others.map(|w| stemmer.stem(w).into_owned()), before);
+        // Only words ending with an added word can change.
+        assert_eq!(stemmer.stem("obcoludzie"), "obcoczłowiek");
+
+        let long = "a".repeat(300);
+        let (word, stem) = (format!("b{}", long), format!("c{}", long));
+        stemmer.add_pair(&word, &stem);
+        assert_eq!(stemmer.stem(&word), stem);
+        assert_eq!(others.map(|w| stemmer.stem(w).into_owned()), before);
+    }
+
+    #[test]
+    fn test_add_pair_keeps_sample() {
+        let mut stemmer = load_stemmer();
+        let words = sample_words(&stemmer);
+        assert!(words.len() > 50_000);
+        let before: Vec<_> = words.iter().map(|w| stemmer.stem(w).into_owned()).collect();
+
+        // The keys which sample words are shortened to by the skips of their first part, which are looked up in the
+        // second trie, are protected. Other words from the sample get a new last char.
+        let mut pairs: Vec<(String, String)> = words
+            .iter()
+            .filter_map(|word| match stemmer.command_for(word)?.first() {
+                Some(&Command::Skip { chars }) => {
+                    let mut key: Vec<char> = word.chars().collect();
+                    key.truncate(key.len().checked_sub(usize::from(chars) + 1)?);
+                    Some(key.into_iter().collect::<String>())
+                }
+                _ => None,
+            })
+            .filter(|key| key.chars().count() >= 4)
+            .map(|key| (key.clone(), key))
+            .collect();
+        assert!(pairs.len() > 50);
+        pairs.extend(words.iter().step_by(4 * 97).map(|word| {
+            let mut stem = word.clone();
+            stem.pop();
+            stem.push('x');
+            (word.clone(), stem)
+        }));
+        pairs.extend(
+            [
+                ("jwyawi", "jwyawi"),
+                ("ludzie", "człowiek"),
+                ("sióstr", "siostr"),
+                ("Wrocławia", "Wrocław"),
+            ]
+            .map(|(word, stem)| (word.into(), stem.into())),
+        );
+        for (word, stem) in &pairs {
+            stemmer.add_pair(word, stem);
+        }
+        let changed = |word: &str| pairs.iter().any(|(added, _)| word.ends_with(&**added));
+        for (idx, (word, stem)) in pairs.iter().enumerate() {
+            // Words which end with a word added later are changed again.
+            if !pairs[idx + 1..]
+                .iter()
+                .any(|(added, _)| word.ends_with(&**added))
+            {
+                assert_eq!(stemmer.stem(word), *stem);
+            }
+        }
+        for (word, before) in words.iter().zip(before) {
+            if !changed(word) {
+                assert_eq!(stemmer.stem(word), before, "{}", word);
+            }
+        }
+    }
+
+    #[test]
+    fn test_stem_into() {
+        let stemmer = load_stemmer();
+        let mut out = String::from("leftover");
+        for word in [
+            "kotami",
+            "książkami",
+            "czytałem",
+            "kot",
+            "Abadan",
+            "ludzie",
+            "zzzz",
+        ] {
+            let changed = stemmer.stem_into(word, &mut out);
+            let stem = stemmer.stem(word);
+            assert_eq!(out, stem);
+            assert_eq!(changed, matches!(stem, Cow::Owned(_)), "{}", word);
+            assert_eq!(changed, out != word);
+        }
+        assert!(!stemmer.stem_into("kot", &mut out));
+        assert_eq!(out, "kot");
+    }
+
+    #[cfg(feature = "table_2000")]
+    #[test]
+    fn test_stem_explain_matches_embedded() {
+        use crate::embedded::STEMMER;
+
+        // Row indices differ between the formats, so only compare the chars of each path.
+        fn without_rows(mut explanation: Explanation) -> Explanation {
+            for stage in &mut explanation.stages {
+                stage.path.iter_mut().for_each(|step| step.row = 0);
+            }
+            explanation
+        }
+
+        let stemmer = load_stemmer();
+        for word in [
+            "kotami",
+            "książkami",
+            "czytałem",
+            "robiliśmy",
+            "ludzieee",
+            "kot",
+            "Abadan",
+        ] {
+            let ours = stemmer.stem_explain(word);
+            assert_eq!(ours.stem, stemmer.stem(word));
+            assert_eq!(
+                without_rows(ours),
+                without_rows(STEMMER.stem_explain(word)),
+                "{}",
+                word
+            );
+        }
+    }
+
+    #[cfg(feature = "table_2000")]
+    #[test]
+    fn test_options_match_embedded() {
+        use crate::embedded::STEMMER;
+
+        let stemmer = load_stemmer();
+        let words = [
+            "kotami",
+            "książkami",
+            "czytałem",
+            "robiliśmy",
+            "ludziom",
+            "pięknymi",
+            "kota",
+            "kot",
+        ];
+        let mut differs = false;
+        for max_stages in 0..4 {
+            for (min_length, min_stem_length) in [(4, 1), (2, 1), (6, 4), (0, 0)] {
+                let options = StemmerOptions::new()
+                    .min_length(min_length)
+                    .max_stages(max_stages)
+                    .min_stem_length(min_stem_length);
+                let ours = options.wrap(&stemmer);
+                let embedded = options.wrap(&STEMMER);
+                for word in words {
+                    assert_eq!(ours.stem(word), embedded.stem(word), "{:?}", options);
+                    differs |= max_stages > 0 && ours.stem(word) != stemmer.stem(word);
+                }
+            }
+        }
+        assert!(differs);
+    }
+
+    #[test]
+    fn test_concurrent_stemming() {
+        use std::{sync::Arc, thread};
+
+        let words = [
+            "kotami",
+            "książkami",
+            "czytałem",
+            "robiliśmy",
+            "ludziom",
+            "pięknymi",
+            "kot",
+        ];
+        let stemmer = Arc::new(load_stemmer());
+        let expected: Vec<String> = words.iter().map(|w| stemmer.stem(w).into_owned()).collect();
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let stemmer = Arc::clone(&stemmer);
+                let expected = expected.clone();
+                thread::spawn(move || {
+                    let mut buffer = crate::StemBuffer::new();
+                    for _ in 0..100 {
+                        for (word, stem) in words.iter().zip(&expected) {
+                            assert_eq!(buffer.stem(&stemmer, word), stem);
+                        }
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+    }
+
+    #[cfg(feature = "table_2000")]
+    #[test]
+    fn test_command_for_matches_embedded() {
+        use crate::embedded::{apply, STEMMER};
+
+        let stemmer = load_stemmer();
+        for word in [
+            "kotami",
+            "książkami",
+            "czytałem",
+            "robiliśmy",
+            "kot",
+            "zzzz",
+        ] {
+            let commands = stemmer.command_for(word);
+            assert_eq!(commands, STEMMER.command_for(word));
+            if let Some(commands) = commands {
+                assert_eq!(apply(word, &commands).unwrap(), stemmer.stem(word));
+            }
+        }
+    }
+
+    #[test]
+    fn test_load_errors() {
+        let mut saved = Vec::new();
+        load_stemmer().save(&mut saved).unwrap();
+        // Cut into the last int, which starts 4 bytes before the end.
+        let truncated = &saved[..saved.len() - 1];
+        let offset = saved.len() as u64 - 4;
+        assert!(matches!(
+            Stemmer::load(truncated),
+            Err(Error::Truncated { offset: o }) if o == offset
+        ));
+
+        let mut bad_method = saved.clone();
+        bad_method[2] = 0xFF;
+        assert!(matches!(
+            Stemmer::load(bad_method.as_slice()),
+            Err(Error::InvalidString { offset: 0 })
+        ));
+
+        // The number of tries follows the 6 byte method string, the `forward` flag and `by`.
+        let mut bad_count = saved.clone();
+        bad_count[11..15].copy_from_slice(&(-1i32).to_be_bytes());
+        assert!(matches!(
+            Stemmer::load(bad_count.as_slice()),
+            Err(Error::NegativeValue { offset: 11 })
+        ));
+    }
+
+    #[test]
+    fn test_load_rejects_invalid_tables() {
+        let load = |trie: Trie| {
+            let mut saved = Vec::new();
+            Stemmer::new("-", Table::Trie(trie))
+                .save(&mut saved)
+                .unwrap();
+            Stemmer::load(saved.as_slice())
+        };
+        let mut trie = Trie::new(false);
+        trie.add("kotami", vec![Command::Delete { chars: 2 }]);
+        assert!(load(trie.clone()).is_ok());
+
+        let mut bad_root = trie.clone();
+        bad_root.root = 100;
+        assert!(matches!(
+            load(bad_root),
+            Err(Error::InvalidRoot { trie: 0, root: 100 })
+        ));
+
+        let mut bad_ref = trie.clone();
+        bad_ref.rows[0].set_ref('i', 100);
+        assert!(matches!(
+            load(bad_ref),
+            Err(Error::InvalidRowReference {
+                trie: 0,
+                row: 0,
+                cell: 'i'
+            })
+        ));
+
+        let mut bad_cmd_index = trie.clone();
+        bad_cmd_index.rows[0].set_cmd('x', 100);
+        assert!(matches!(
+            load(bad_cmd_index),
+            Err(Error::InvalidCommandIndex {
+                trie: 0,
+                row: 0,
+                cell: 'x'
+            })
+        ));
+
+        // Replace the patch string `Dc`, which is parsed while loading.
+        let mut bad_cmd = Vec::new();
+        Stemmer::new("-", Table::Trie(trie.clone()))
+            .save(&mut bad_cmd)
+            .unwrap();
+        let idx = bad_cmd.windows(4).position(|w| w == b"\0\x02Dc").unwrap();
+        bad_cmd[idx + 2..idx + 4].copy_from_slice(b"Xa");
+        let err = Stemmer::load(bad_cmd.as_slice()).err().unwrap();
+        assert!(matches!(
+            &err,
+            Error::UnknownOpcode {
+                trie: 0,
+                index: 0,
+                offset,
+                cell: Some((5, 'k')),
+                command,
+                opcode: 'X',
+            } if *offset == idx as u64 && command == "Xa"
+        ));
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "unknown opcode 'X' in command 0 \"Xa\" of trie 0 at byte {}, used by cell 'k' of row 5",
+                idx
+            )
+        );
+
+        // Commands which no cell refers to are reported without one.
+        let mut unused = trie.clone();
+        unused
+            .cmds
+            .push(Patch::Commands([Command::Insert { char: 'q' }].into()));
+        let mut bad_cmd = Vec::new();
+        Stemmer::new("-", Table::Trie(unused))
+            .save(&mut bad_cmd)
+            .unwrap();
+        let idx = bad_cmd.windows(4).position(|w| w == b"\0\x02Iq").unwrap();
+        bad_cmd[idx + 2..idx + 4].copy_from_slice(b"D!");
+        let err = Stemmer::load(bad_cmd.as_slice()).err().unwrap();
+        assert!(matches!(
+            err,
+            Error::InvalidParameter {
+                trie: 0,
+                index: 1,
+                cell: None,
+                param: Some('!'),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_load_huge_counts() {
+        // A trie claiming i32::MAX commands, which ends right after the count.
+        let mut table = Vec::new();
+        table.extend_from_slice(&[0, 1, b'-', 0]);
+        table.extend_from_slice(&0i32.to_be_bytes());
+        table.extend_from_slice(&i32::MAX.to_be_bytes());
+        assert!(matches!(
+            Stemmer::load(table.as_slice()),
+            Err(Error::Truncated { offset: 12 })
+        ));
+    }
+
     #[test]
     fn test_compare_stem_to_stempel() {
-        let file = fs::File::open("src/tables/polimorf_words_stemmed.tab.gz").unwrap();
-        let mut reader = io::BufReader::new(GzDecoder::new(io::BufReader::new(file)));
+        let mut reader = open_gz("src/tables/polimorf_words_stemmed.tab.gz");
         let mut line = String::new();
 
-        let input = fs::File::open("src/tables/stemmer_2000.out.gz").unwrap();
-        let input = io::BufReader::new(GzDecoder::new(io::BufReader::new(input)));
-        let stemmer = Stemmer::load(input).unwrap();
+        let stemmer = load_stemmer();
 
         let mut num = 0;
         while reader.read_line(&mut line).unwrap() > 0 {
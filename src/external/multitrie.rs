@@ -1,136 +1,372 @@
 use super::serialize::*;
-use super::trie::{Trie, TrieGet};
+use super::trie::{Patch, Trie, TrieGet};
+use crate::command::length_pp;
+use crate::explain::{Decision, Trace};
+use crate::{Command, Error};
 use std::io;
 
+#[derive(Debug, Clone)]
 pub struct MultiTrie {
     pub tries: Vec<Trie>,
-    #[allow(dead_code)]
     pub forward: bool,
-    #[allow(dead_code)]
     pub by: i32,
 }
 
 impl JavaDeserialize for MultiTrie {
-    fn deserialize<R: io::Read>(reader: &mut DataInput<R>) -> io::Result<Self> {
+    fn deserialize<R: io::Read>(reader: &mut DataInput<R>) -> Result<Self, Error> {
         let forward = reader.read_bool()?;
         let by = reader.read_i32()?;
         let count = reader.read_usize()?;
-        let mut tries = Vec::with_capacity(count);
-        for _ in 0..count {
-            tries.push(Trie::deserialize(reader)?);
+        let mut tries = Vec::with_capacity(capacity_for(count));
+        for idx in 0..count {
+            tries.push(Trie::deserialize_in(reader, idx)?);
         }
         Ok(Self { tries, forward, by })
     }
 }
 
+impl JavaSerialize for MultiTrie {
+    fn serialize<W: io::Write>(&self, writer: &mut DataOutput<W>) -> io::Result<()> {
+        writer.write_bool(self.forward)?;
+        writer.write_i32(self.by)?;
+        writer.write_usize(self.tries.len())?;
+        for trie in &self.tries {
+            writer.write(trie)?;
+        }
+        Ok(())
+    }
+}
+
+impl MultiTrie {
+    /// Checks every trie with [`Trie::validate`].
+    pub fn validate(&self) -> Result<(), Error> {
+        for (idx, trie) in self.tries.iter().enumerate() {
+            trie.validate_in(idx)?;
+        }
+        Ok(())
+    }
+}
+
 impl TrieGet for MultiTrie {
     fn get_cmd(&self, key: &str) -> Option<String> {
         let mut result = String::with_capacity(self.tries.len() * 2);
         for trie in &self.tries {
-            let r = trie.get_cmd(key)?;
-            if r == "*" {
-                return Some(result);
+            match trie.get_cmd_ref(key)? {
+                Patch::Commands(commands) => {
+                    commands.iter().for_each(|cmd| cmd.push_to(&mut result))
+                }
+                Patch::End => break,
             }
-            result.push_str(&r);
         }
         Some(result)
     }
 }
 
+#[derive(Debug, Clone)]
 pub struct MultiTrie2 {
     pub t: MultiTrie,
 }
 
 impl JavaDeserialize for MultiTrie2 {
-    fn deserialize<R: io::Read>(reader: &mut DataInput<R>) -> io::Result<Self> {
+    fn deserialize<R: io::Read>(reader: &mut DataInput<R>) -> Result<Self, Error> {
         let t = reader.read()?;
         Ok(Self { t })
     }
 }
 
-fn cannot_follow(after: char, goes: char) -> bool {
-    match after {
-        '-' | 'D' => after == goes,
-        _ => false,
+impl JavaSerialize for MultiTrie2 {
+    fn serialize<W: io::Write>(&self, writer: &mut DataOutput<W>) -> io::Result<()> {
+        writer.write(&self.t)
     }
 }
 
-fn length_pp(cmd: &str) -> usize {
-    let mut len = 0;
-    let mut iter = cmd.chars();
-    while let (Some(cmd), Some(arg)) = (iter.next(), iter.next()) {
-        match (cmd, arg) {
-            ('-' | 'D', c) => {
-                assert!(c.is_ascii_lowercase());
-                len += 1 + ((c as u8) - b'a') as usize;
+impl MultiTrie2 {
+    /// Checks every trie with [`Trie::validate`].
+    pub fn validate(&self) -> Result<(), Error> {
+        self.t.validate()
+    }
+
+    /// Creates an empty multi-trie which reads keys from the start if `forward`, or from the end otherwise.
+    pub fn new(forward: bool) -> Self {
+        Self {
+            t: MultiTrie {
+                tries: Vec::new(),
+                forward,
+                by: 1,
+            },
+        }
+    }
+
+    /// Stores the commands `cmd` for `key`, splitting them across the tries the same way
+    /// [`TrieGet::get_cmd`] will reassemble them.
+    pub fn add(&mut self, key: &str, cmd: &[Command]) {
+        if cmd.is_empty() {
+            return;
+        }
+        let forward = self.t.forward;
+        let parts = decompose(cmd);
+        while parts.len() >= self.t.tries.len() {
+            self.t.tries.push(Trie::new(forward));
+        }
+        let mut key = key;
+        let mut last_key = key;
+        for (i, &part) in parts.iter().enumerate() {
+            if !key.is_empty() {
+                last_key = key;
             }
-            ('R', _) => len += 1,
-            _ => {}
+            self.t.tries[i].add(last_key, part);
+            if part[0].is_skip() {
+                if i > 0 {
+                    key = skip(forward, key, length_pp(parts[i - 1])).unwrap_or_default();
+                }
+                key = skip(forward, key, length_pp(part)).unwrap_or_default();
+            }
+        }
+        if !key.is_empty() {
+            last_key = key;
         }
+        self.t.tries[parts.len()].add(last_key, Patch::End);
+    }
+
+    /// Stores the commands `cmd` for `key` without changing the commands for keys which don't contain `key`. See
+    /// [`Trie::patch`].
+    ///
+    /// Unlike [`MultiTrie2::add`] every command is stored in the first trie, because the shortened keys used by later
+    /// tries are shared with other words, which must keep their commands. The commands are chosen to make the lookup
+    /// of `key` stop after the first trie, by ending with a command which the next part can't follow, or by covering
+    /// enough of the key that the next part's skip runs past it. Only if no such commands exist, such as when the next
+    /// part inserts chars and `cmd` changes the last char of `key`, is an end marker stored for `key` in the second
+    /// trie. That also stops the words which are looked up there with a key ending with `key`.
+    pub fn patch(&mut self, key: &str, cmd: &[Command]) {
+        if key.is_empty() {
+            return;
+        }
+        while self.t.tries.len() < 2 {
+            self.t.tries.push(Trie::new(self.t.forward));
+        }
+        if cmd.is_empty() {
+            self.t.tries[0].patch(key, Patch::End);
+            return;
+        }
+        match patch_candidates(key, cmd).find(|first| self.stops_after(key, first)) {
+            Some(first) => self.t.tries[0].patch(key, first.as_slice()),
+            None => {
+                // Without a leading skip the second trie is looked up with the whole key.
+                let first = patch_candidates(key, cmd).find(|first| !first[0].is_skip());
+                self.t.tries[0].patch(key, first.unwrap().as_slice());
+                self.t.tries[1].patch(key, Patch::End);
+            }
+        }
+    }
+
+    /// True if the lookup of `key` stops after the first trie, when that returns `first`.
+    fn stops_after(&self, key: &str, first: &[Command]) -> bool {
+        let trie = match self.t.tries.get(1) {
+            Some(trie) => trie,
+            None => return true,
+        };
+        let mut key = key;
+        let mut last_key = key;
+        if first[0].is_skip() {
+            key = match skip(self.t.forward, key, length_pp(first)) {
+                Some(key) => key,
+                None => return false,
+            };
+        }
+        if !key.is_empty() {
+            last_key = key;
+        }
+        let mut last_cmd = first.last().copied();
+        let mut prev_cmd = Some(first);
+        get_cmd_(
+            trie,
+            &mut key,
+            last_key,
+            &mut last_cmd,
+            &mut prev_cmd,
+            &mut (),
+        )
+        .is_err()
     }
-    len
 }
 
-fn skip<'a>(trie: &Trie, i: &'a str, cnt: usize) -> Option<&'a str> {
-    if cnt == 0 {
-        return Some(i);
+/// Returns the commands which could be stored for `key` in the first trie by [`MultiTrie2::patch`], which all have the
+/// same effect as `cmd`, shortest first. Some of them don't start with a skip. They are built as they are needed, since
+/// there are a few for every char of the key.
+fn patch_candidates<'a>(key: &str, cmd: &'a [Command]) -> impl Iterator<Item = Vec<Command>> + 'a {
+    let chars: Vec<char> = key.chars().collect();
+    let skips = cmd.iter().take_while(|cmd| cmd.is_skip()).count();
+    let skipped = length_pp(&cmd[..skips]).min(chars.len());
+    // Leading skips would shorten the key used for the second trie, so replace the skipped chars with themselves.
+    let replaced: Vec<Command> = chars[chars.len() - skipped..]
+        .iter()
+        .rev()
+        .map(|&char| Command::Replace { char })
+        .chain(cmd[skips..].iter().copied())
+        .collect();
+    let mut forms = vec![replaced];
+    if skips > 0 {
+        forms.push(cmd.to_vec());
     }
-    let mut iter = i.char_indices();
-    let mut start = 0;
-    let mut end = i.len();
-    if trie.forward {
-        for _ in 0..cnt {
-            start = iter.next()?.0;
+    // Each candidate is a form, followed by the chars before the ones it covers replaced with themselves to cover more
+    // of the key, and optionally an ending: a trailing skip, or replacing the next char by inserting a copy of it and
+    // deleting it, so that it ends with a delete.
+    let mut specs = Vec::new();
+    for (idx, form) in forms.iter().enumerate() {
+        let rest = chars.len().saturating_sub(length_pp(form));
+        for covered in 0..=rest {
+            let len = form.len() + covered;
+            if covered > 0 || form.last().is_some_and(|cmd| !cmd.is_skip()) {
+                specs.push((len + 1, idx, covered, 0));
+            }
+            if covered < rest {
+                specs.push((len + 2, idx, covered, 1));
+            }
+            specs.push((len, idx, covered, 2));
         }
-    } else {
-        for _ in 0..cnt {
-            end = iter.next_back()?.0;
+    }
+    specs.sort_unstable();
+    specs.into_iter().map(move |(_, idx, covered, ending)| {
+        let form = &forms[idx];
+        let rest = &chars[..chars.len().saturating_sub(length_pp(form))];
+        let mut candidate = form.clone();
+        candidate.extend(
+            rest[rest.len() - covered..]
+                .iter()
+                .rev()
+                .map(|&char| Command::Replace { char }),
+        );
+        match ending {
+            0 => candidate.push(Command::Skip { chars: 0 }),
+            1 => candidate.extend([
+                Command::Insert {
+                    char: rest[rest.len() - covered - 1],
+                },
+                Command::Delete { chars: 0 },
+            ]),
+            _ => {}
+        }
+        candidate
+    })
+}
+
+/// Removes `cnt` chars from the start of the key if `forward`, else from the end.
+fn skip(forward: bool, key: &str, cnt: usize) -> Option<&str> {
+    let mut iter = key.chars();
+    for _ in 0..cnt {
+        if forward {
+            iter.next()?;
+        } else {
+            iter.next_back()?;
+        }
+    }
+    Some(iter.as_str())
+}
+
+/// Splits the commands into the parts stored in consecutive tries: each skip command on its own, and the runs of other
+/// commands between them. Consecutive skips, which cover a run too long for a single command, share a part, since a
+/// skip can't follow a skip in the next trie.
+fn decompose(cmd: &[Command]) -> Vec<&[Command]> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    for (idx, command) in cmd.iter().enumerate() {
+        if command.is_skip() {
+            if start < idx {
+                parts.push(&cmd[start..idx]);
+            }
+            let end = idx + cmd[idx..].iter().take_while(|cmd| cmd.is_skip()).count();
+            if idx == 0 || !cmd[idx - 1].is_skip() {
+                parts.push(&cmd[idx..end]);
+            }
+            start = end;
         }
     }
-    Some(&i[start..end])
+    if start < cmd.len() {
+        parts.push(&cmd[start..]);
+    }
+    parts
 }
 
-fn get_cmd_(
-    trie: &Trie,
+/// Returns the next part of the command for `key` from `trie`, or why stemming stops at it and the commands found
+/// there.
+fn get_cmd_<'a>(
+    trie: &'a Trie,
     key: &mut &str,
     last_key: &str,
-    last_ch: &mut char,
-    prev_cmd: &mut Option<String>,
-) -> Option<String> {
-    let r = trie.get_cmd(last_key)?;
-    if r == "*" {
-        return None;
+    last_cmd: &mut Option<Command>,
+    prev_cmd: &mut Option<&'a [Command]>,
+    trace: &mut impl Trace<&'a [Command]>,
+) -> Result<&'a [Command], (Option<&'a [Command]>, Decision)> {
+    let r = match trie.get_cmd_traced(last_key, trace) {
+        Some(Patch::Commands(r)) => &**r,
+        Some(Patch::End) => return Err((None, Decision::EndOfCommand)),
+        None => return Err((None, Decision::NotFound)),
+    };
+    let (first, last) = match (r.first(), r.last()) {
+        (Some(&first), Some(&last)) => (first, last),
+        _ => return Err((Some(r), Decision::InvalidCommand)),
+    };
+    if last_cmd.is_some_and(|lc| first.cannot_follow(lc)) {
+        return Err((Some(r), Decision::CannotFollow));
     }
-    if cannot_follow(*last_ch, r.chars().next()?) {
-        return None;
-    } else {
-        *last_ch = r.chars().nth_back(1)?;
-    }
-    if r.starts_with('-') {
-        if let Some(prev_cmd) = prev_cmd.as_ref() {
-            *key = skip(trie, key, length_pp(prev_cmd))?;
+    *last_cmd = Some(last);
+    if first.is_skip() {
+        let too_short = (Some(r), Decision::KeyTooShort);
+        if let Some(prev_cmd) = *prev_cmd {
+            *key = skip(trie.forward, key, length_pp(prev_cmd)).ok_or(too_short)?;
         }
-        *key = skip(trie, key, length_pp(&r))?;
+        *key = skip(trie.forward, key, length_pp(r)).ok_or(too_short)?;
     }
-    *prev_cmd = Some(r.clone());
-    Some(r)
+    *prev_cmd = Some(r);
+    Ok(r)
 }
 
-impl TrieGet for MultiTrie2 {
-    fn get_cmd(&self, mut key: &str) -> Option<String> {
-        let mut result = String::with_capacity(self.t.tries.len() * 2);
+impl MultiTrie2 {
+    /// Like [`TrieGet::get_cmd`], but passes each part of the command to `visit` instead of concatenating them.
+    pub fn visit_cmd<'a>(&'a self, key: &str, visit: impl FnMut(&'a [Command])) {
+        self.trace_cmd(key, usize::MAX, visit, &mut ());
+    }
+
+    /// Like [`MultiTrie2::visit_cmd`], but only consults the first `max_stages` tries, and passes each to `trace`.
+    pub(crate) fn trace_cmd<'a>(
+        &'a self,
+        mut key: &str,
+        max_stages: usize,
+        mut visit: impl FnMut(&'a [Command]),
+        trace: &mut impl Trace<&'a [Command]>,
+    ) {
         let mut last_key = key;
         let mut prev_cmd = None;
-        let mut last_ch = ' ';
-        for trie in &self.t.tries {
-            match get_cmd_(trie, &mut key, last_key, &mut last_ch, &mut prev_cmd) {
-                None => break,
-                Some(r) => result.push_str(&r),
+        let mut last_cmd = None;
+        for (idx, trie) in self.t.tries.iter().enumerate().take(max_stages) {
+            match get_cmd_(
+                trie,
+                &mut key,
+                last_key,
+                &mut last_cmd,
+                &mut prev_cmd,
+                trace,
+            ) {
+                Err((r, decision)) => {
+                    trace.stage(idx, last_key, r, decision);
+                    break;
+                }
+                Ok(r) => {
+                    trace.stage(idx, last_key, Some(r), Decision::Used);
+                    visit(r);
+                }
             }
             if !key.is_empty() {
                 last_key = key;
             }
         }
+    }
+}
+
+impl TrieGet for MultiTrie2 {
+    fn get_cmd(&self, key: &str) -> Option<String> {
+        let mut result = String::with_capacity(self.t.tries.len() * 2);
+        self.visit_cmd(key, |r| r.iter().for_each(|cmd| cmd.push_to(&mut result)));
         Some(result)
     }
 }
@@ -138,15 +374,11 @@ impl TrieGet for MultiTrie2 {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use flate2::bufread::GzDecoder;
-    use std::fs;
+    use crate::test_util::{open_gz, STEMMER_2000};
 
     #[test]
     fn test_lookup_multi2() {
-        let path = "src/tables/stemmer_2000.out.gz";
-        let input = fs::File::open(path).unwrap();
-        let input = io::BufReader::new(GzDecoder::new(io::BufReader::new(input)));
-        let mut reader = DataInput::new(input);
+        let mut reader = DataInput::new(open_gz(STEMMER_2000));
         let params = reader.read_string().unwrap();
         assert!(params.contains('M'));
         let trie = MultiTrie2::deserialize(&mut reader).unwrap();
@@ -0,0 +1,529 @@
+//! Passes which shrink a [`Trie`] after training, ported from Egothor's `Reduce` family of classes.
+
+use super::multitrie::MultiTrie2;
+use super::trie::{Cell, Row, Trie};
+use super::Table;
+
+/// An optimization pass over a trie. Every pass except [`Reduce::Gener`] keeps the command of every key stored in the
+/// trie, but may change the command found for other keys.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reduce {
+    /// Removes rows which can't be reached from the root, renumbering the rest depth-first. Egothor's `Reduce`.
+    RemoveGaps,
+    /// Merges rows which have no conflicting cells, where a missing command or reference doesn't conflict with
+    /// anything. Egothor's `Optimizer`, method char `1`.
+    Optimize,
+    /// Merges rows whose shared cells are identical. Egothor's `Optimizer2`, method char `2`.
+    Optimize2,
+    /// Replaces references to rows which only hold a single command with that command. If `change_skip` the number of
+    /// chars that the lifted cell skips is incremented. Egothor's `Lift`, method char `L` with `change_skip` and `E`
+    /// without.
+    Lift { change_skip: bool },
+    /// Removes commands which were used less than a tenth as often as the other commands in their row, along with rows
+    /// left empty. Egothor's `Gener`, method char `G`.
+    Gener,
+}
+
+impl Reduce {
+    /// Returns the pass selected by a char of a Java `Compile` method string, if any.
+    pub fn from_method_char(ch: char) -> Option<Self> {
+        match ch {
+            'G' => Some(Self::Gener),
+            'L' => Some(Self::Lift { change_skip: true }),
+            'E' => Some(Self::Lift { change_skip: false }),
+            '2' => Some(Self::Optimize2),
+            '1' => Some(Self::Optimize),
+            _ => None,
+        }
+    }
+
+    pub fn optimize(self, trie: Trie) -> Trie {
+        match self {
+            Self::RemoveGaps => remove_gaps(trie),
+            Self::Optimize => merge_rows(trie, merge_compatible),
+            Self::Optimize2 => merge_rows(trie, merge_equal),
+            Self::Lift { change_skip } => lift(trie, change_skip),
+            Self::Gener => gener(trie),
+        }
+    }
+}
+
+impl Trie {
+    pub fn reduce(self, by: Reduce) -> Self {
+        by.optimize(self)
+    }
+}
+
+impl MultiTrie2 {
+    pub fn reduce(mut self, by: Reduce) -> Self {
+        self.t.tries = self.t.tries.into_iter().map(|t| t.reduce(by)).collect();
+        self
+    }
+}
+
+impl Table {
+    pub fn reduce(self, by: Reduce) -> Self {
+        match self {
+            Table::Trie(trie) => Table::Trie(trie.reduce(by)),
+            Table::MultiTrie2(trie) => Table::MultiTrie2(trie.reduce(by)),
+        }
+    }
+}
+
+fn remap_row(row: &Row, remap: impl Fn(u32) -> u32) -> Row {
+    let mut row = row.clone();
+    for cell in row.cells.values_mut() {
+        if let Some(refr) = cell.refr.as_mut() {
+            *refr = remap(*refr);
+        }
+    }
+    row
+}
+
+/// Walks the rows reachable from the root depth-first, calling `pre` when a row is first reached and `post` once
+/// every row it references has been walked.
+fn depth_first(trie: &Trie, mut pre: impl FnMut(usize), mut post: impl FnMut(usize)) {
+    let mut visited = vec![false; trie.rows.len()];
+    let root = trie.root as usize;
+    visited[root] = true;
+    pre(root);
+    let mut stack = vec![(root, 0)];
+    while let Some(&mut (index, ref mut next)) = stack.last_mut() {
+        let refr = trie.rows[index]
+            .cells
+            .values()
+            .filter_map(|c| c.refr)
+            .nth(*next);
+        *next += 1;
+        match refr.map(|r| r as usize) {
+            Some(r) if !visited[r] => {
+                visited[r] = true;
+                pre(r);
+                stack.push((r, 0));
+            }
+            Some(_) => {}
+            None => {
+                post(index);
+                stack.pop();
+            }
+        }
+    }
+}
+
+fn remove_gaps(trie: Trie) -> Trie {
+    let mut remap = vec![0; trie.rows.len()];
+    let mut order = Vec::with_capacity(trie.rows.len());
+    depth_first(
+        &trie,
+        |index| {
+            remap[index] = order.len() as u32;
+            order.push(index);
+        },
+        |_| {},
+    );
+    let rows = order
+        .into_iter()
+        .map(|index| remap_row(&trie.rows[index], |r| remap[r as usize]))
+        .collect();
+    Trie {
+        rows,
+        root: remap[trie.root as usize],
+        ..trie
+    }
+}
+
+/// The order in which rows are optimized, so that rows are generally visited after the rows they reference. Tries
+/// built by [`Trie::add`] only reference later rows, so like the Java implementation we go in reverse. Tables which
+/// have already been optimized can share rows, and even contain cycles, so we fall back to a depth-first post-order.
+fn bottom_up_order(trie: &Trie) -> Vec<usize> {
+    let refs_forward = trie.rows.iter().enumerate().all(|(index, row)| {
+        row.cells
+            .values()
+            .all(|c| c.refr.is_none_or(|r| r as usize > index))
+    });
+    if refs_forward {
+        return (0..trie.rows.len()).rev().collect();
+    }
+    let mut order = Vec::with_capacity(trie.rows.len());
+    depth_first(trie, |_| {}, |index| order.push(index));
+    order
+}
+
+fn merge_compatible(m: &Cell, e: &Cell) -> Option<Cell> {
+    fn merge(m: Option<u32>, e: Option<u32>) -> Option<Option<u32>> {
+        match (m, e) {
+            (Some(m), Some(e)) if m != e => None,
+            (m, e) => Some(m.or(e)),
+        }
+    }
+
+    if m.skip != e.skip {
+        return None;
+    }
+    Some(Cell {
+        cmd: merge(m.cmd, e.cmd)?,
+        refr: merge(m.refr, e.refr)?,
+        cnt: m.cnt + e.cnt,
+        skip: m.skip,
+    })
+}
+
+fn merge_equal(m: &Cell, e: &Cell) -> Option<Cell> {
+    if m.cmd == e.cmd && m.refr == e.refr && m.skip == e.skip {
+        Some(Cell {
+            cnt: m.cnt + e.cnt,
+            ..m.clone()
+        })
+    } else {
+        None
+    }
+}
+
+fn can_merge(master: &Row, existing: &Row, merge_cell: fn(&Cell, &Cell) -> Option<Cell>) -> bool {
+    master.cells.iter().all(|(ch, a)| {
+        existing
+            .cells
+            .get(ch)
+            .is_none_or(|b| merge_cell(a, b).is_some())
+    })
+}
+
+fn merge_row(master: &Row, existing: &mut Row, merge_cell: fn(&Cell, &Cell) -> Option<Cell>) {
+    for (&ch, a) in &master.cells {
+        let cell = match existing.cells.get(&ch) {
+            Some(b) => merge_cell(a, b).unwrap(),
+            None => a.clone(),
+        };
+        existing.cells.insert(ch, cell);
+    }
+}
+
+fn merge_rows(trie: Trie, merge_cell: fn(&Cell, &Cell) -> Option<Cell>) -> Trie {
+    // References to rows which haven't been merged yet point past the end of the new rows until they are fixed up.
+    let pending = trie.rows.len() as u32;
+    let mut remap = vec![None; trie.rows.len()];
+    let mut rows: Vec<Row> = Vec::new();
+    for j in bottom_up_order(&trie) {
+        let now = remap_row(&trie.rows[j], |r| remap[r as usize].unwrap_or(pending + r));
+        match rows.iter().position(|row| can_merge(&now, row, merge_cell)) {
+            Some(i) => {
+                merge_row(&now, &mut rows[i], merge_cell);
+                remap[j] = Some(i as u32);
+            }
+            None => {
+                remap[j] = Some(rows.len() as u32);
+                rows.push(now);
+            }
+        }
+    }
+    let resolve = |r: u32| match r.checked_sub(pending) {
+        Some(r) => remap[r as usize].unwrap(),
+        None => r,
+    };
+    let rows = rows.iter().map(|row| remap_row(row, resolve)).collect();
+    remove_gaps(Trie {
+        rows,
+        root: remap[trie.root as usize].unwrap(),
+        ..trie
+    })
+}
+
+/// If every command in the row is the same and there are no references, returns the command, the number of cells
+/// which have it, and their skip count.
+fn uniform_cmd(row: &Row, eq_skip: bool) -> Option<(u32, u32, u32)> {
+    let mut result: Option<(u32, u32, u32)> = None;
+    for cell in row.cells.values() {
+        if cell.refr.is_some() {
+            return None;
+        }
+        if let Some(cmd) = cell.cmd {
+            match result.as_mut() {
+                None => result = Some((cmd, 1, cell.skip)),
+                Some((uniform, cnt, skip))
+                    if *uniform == cmd && (!eq_skip || *skip == cell.skip) =>
+                {
+                    *cnt += 1
+                }
+                Some(_) => return None,
+            }
+        }
+    }
+    result
+}
+
+fn lift(mut trie: Trie, change_skip: bool) -> Trie {
+    for j in bottom_up_order(&trie) {
+        let uniform = trie.rows[j]
+            .cells
+            .values()
+            .map(|c| {
+                c.refr
+                    .and_then(|r| uniform_cmd(&trie.rows[r as usize], change_skip))
+            })
+            .collect::<Vec<_>>();
+        for (cell, uniform) in trie.rows[j].cells.values_mut().zip(uniform) {
+            let (cmd, cnt, skip) = match uniform {
+                Some(u) => u,
+                None => continue,
+            };
+            if cell.cmd == Some(cmd) {
+                if change_skip {
+                    if cell.skip != skip + 1 {
+                        continue;
+                    }
+                } else {
+                    cell.skip = 0;
+                }
+                cell.cnt += cnt;
+                cell.refr = None;
+            } else if cell.cmd.is_none() {
+                cell.cnt = cnt;
+                cell.cmd = Some(cmd);
+                cell.refr = None;
+                cell.skip = if change_skip { skip + 1 } else { 0 };
+            }
+        }
+    }
+    remove_gaps(trie)
+}
+
+fn gener(mut trie: Trie) -> Trie {
+    let mut dead = vec![false; trie.rows.len()];
+    for j in bottom_up_order(&trie) {
+        let row = &mut trie.rows[j];
+        let mut sum = 0;
+        for cell in row.cells.values_mut() {
+            sum += cell.cnt;
+            if cell.refr.is_some_and(|r| dead[r as usize]) {
+                cell.refr = None;
+            }
+        }
+        let frame = sum / 10;
+        let mut live = false;
+        for cell in row.cells.values_mut() {
+            if cell.cnt < frame && cell.cmd.is_some() {
+                cell.cnt = 0;
+                cell.cmd = None;
+            }
+            live |= cell.cmd.is_some() || cell.refr.is_some();
+        }
+        dead[j] = !live;
+    }
+    remove_gaps(trie)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::external::serialize::{DataInput, JavaDeserialize};
+    use crate::external::trie::{Patch, TrieGet};
+    use crate::external::Stemmer;
+    use crate::test_util::{load_stemmer, open_gz, stored_words, STEMMER_2000};
+    use crate::Stem;
+
+    fn patch(cmd: &str) -> Patch {
+        Patch::parse(cmd).unwrap()
+    }
+
+    fn load_multitrie() -> MultiTrie2 {
+        let mut reader = DataInput::new(open_gz(STEMMER_2000));
+        reader.read_string().unwrap();
+        MultiTrie2::deserialize(&mut reader).unwrap()
+    }
+
+    /// Returns the keys of commands stored in the trie, up to a limit since rows can be shared.
+    fn stored_keys(trie: &Trie) -> Vec<String> {
+        fn visit(
+            trie: &Trie,
+            row: u32,
+            path: &mut Vec<char>,
+            depth: usize,
+            keys: &mut Vec<String>,
+        ) {
+            for (&ch, cell) in &trie.rows[row as usize].cells {
+                if keys.len() >= 20_000 {
+                    return;
+                }
+                path.push(ch);
+                if cell.cmd.is_some() {
+                    let key = path.iter();
+                    keys.push(if trie.forward {
+                        key.collect()
+                    } else {
+                        key.rev().collect()
+                    });
+                }
+                if let Some(refr) = cell.refr.filter(|_| depth < 8) {
+                    visit(trie, refr, path, depth + 1, keys);
+                }
+                path.pop();
+            }
+        }
+
+        let mut keys = Vec::new();
+        visit(trie, trie.root, &mut Vec::new(), 0, &mut keys);
+        keys
+    }
+
+    /// Returns the command which [`Reduce::Gener`] should leave for `key`: the last one on its path which was used at
+    /// least a tenth as often as the cells of its row together.
+    fn common_cmd(trie: &Trie, key: &str) -> Option<String> {
+        let mut chars: Vec<char> = key.chars().collect();
+        if !trie.forward {
+            chars.reverse();
+        }
+        let mut row = &trie.rows[trie.root as usize];
+        let mut last = None;
+        for ch in chars {
+            let cell = match row.cells.get(&ch) {
+                Some(cell) => cell,
+                None => break,
+            };
+            let sum: u32 = row.cells.values().map(|cell| cell.cnt).sum();
+            if cell.cmd.is_some() && cell.cnt >= sum / 10 {
+                last = cell.cmd;
+            }
+            match cell.refr {
+                Some(refr) => row = &trie.rows[refr as usize],
+                None => break,
+            }
+        }
+        last.map(|cmd| trie.cmds[cmd as usize].to_string())
+    }
+
+    fn assert_keeps_stored_keys(orig: &MultiTrie2, by: Reduce) {
+        let reduced = orig.clone().reduce(by);
+        for (orig, reduced) in orig.t.tries.iter().zip(&reduced.t.tries) {
+            for key in stored_keys(orig) {
+                assert_eq!(
+                    orig.get_cmd(&key),
+                    reduced.get_cmd(&key),
+                    "{:?} {}",
+                    by,
+                    key
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_reductions_keep_stored_keys() {
+        let multitrie = load_multitrie();
+        for by in [
+            Reduce::RemoveGaps,
+            Reduce::Optimize,
+            Reduce::Optimize2,
+            Reduce::Lift { change_skip: true },
+            Reduce::Lift { change_skip: false },
+        ] {
+            assert_keeps_stored_keys(&multitrie, by);
+        }
+    }
+
+    #[test]
+    fn test_reductions_keep_stems() {
+        let stemmer = load_stemmer();
+        let words = stored_words(&stemmer);
+        let before: Vec<_> = words.iter().map(|w| stemmer.stem(w)).collect();
+        for by in [
+            Reduce::RemoveGaps,
+            Reduce::Optimize,
+            Reduce::Optimize2,
+            Reduce::Lift { change_skip: true },
+            Reduce::Lift { change_skip: false },
+        ] {
+            let reduced = Stemmer::new(stemmer.method(), stemmer.table().clone().reduce(by));
+            for (word, before) in words.iter().zip(&before) {
+                assert_eq!(&reduced.stem(word), before, "{:?} {}", by, word);
+            }
+        }
+    }
+
+    #[test]
+    fn test_reductions_shrink_trained_trie() {
+        let words = [
+            "kota", "kotu", "kotem", "kocie", "koty", "kotów", "kotom", "kotami", "kotach", "nosa",
+            "nosu", "nosem", "nosie", "nosy", "nosów", "nosom", "nosami", "nosach",
+        ];
+        let mut trie = Trie::new(false);
+        for word in words {
+            let stem = word.chars().take(3).collect::<String>();
+            trie.add(word, crate::external::diff::compute_commands(word, &stem));
+        }
+        for by in ['1', '2', 'L', 'E'] {
+            let by = Reduce::from_method_char(by).unwrap();
+            let reduced = trie.clone().reduce(by);
+            assert!(reduced.rows.len() < trie.rows.len(), "{:?}", by);
+            for word in words {
+                assert_eq!(
+                    trie.get_cmd(word),
+                    reduced.get_cmd(word),
+                    "{:?} {}",
+                    by,
+                    word
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_remove_gaps() {
+        let mut trie = Trie::new(false);
+        trie.add("ab", patch("Da"));
+        trie.rows.push(Row::default());
+        let reduced = trie.reduce(Reduce::RemoveGaps);
+        assert_eq!(reduced.rows.len(), 2);
+        assert_eq!(reduced.get_cmd("ab").as_deref(), Some("Da"));
+    }
+
+    #[test]
+    fn test_gener_drops_rare_commands() {
+        let mut trie = Trie::new(false);
+        trie.add("a", patch("Da"));
+        trie.add("b", patch("Db"));
+        trie.add("c", patch("Da"));
+        trie.add("xd", patch("Dc"));
+        trie.add("yd", patch("Dd"));
+        // Twenty cells which were each used once, so all of them are dropped, along with their row.
+        for ch in 'a'..='t' {
+            trie.add(&format!("{}e", ch), patch("De"));
+        }
+        let root = &mut trie.rows[0];
+        root.cells.get_mut(&'a').unwrap().cnt = 50;
+        root.cells.get_mut(&'b').unwrap().cnt = 3;
+        root.cells.get_mut(&'c').unwrap().cnt = 10;
+        let d = root.get_ref('d').unwrap() as usize;
+        trie.rows[d].cells.get_mut(&'y').unwrap().cnt = 30;
+
+        let reduced = trie.reduce(Reduce::Gener);
+        assert_eq!(reduced.get_cmd("a").as_deref(), Some("Da"));
+        assert_eq!(reduced.get_cmd("b"), None);
+        assert_eq!(reduced.get_cmd("c").as_deref(), Some("Da"));
+        assert_eq!(reduced.get_cmd("xd"), None);
+        assert_eq!(reduced.get_cmd("yd").as_deref(), Some("Dd"));
+        assert_eq!(reduced.get_cmd("ae"), None);
+        assert_eq!(reduced.rows.len(), 2);
+    }
+
+    #[test]
+    fn test_gener_on_table() {
+        let multitrie = load_multitrie();
+        let reduced = multitrie.clone().reduce(Reduce::Gener);
+        // Only the commands of rarely used cells are dropped, so keys either keep their command or fall back to a shorter
+        // key's.
+        let (mut kept, mut changed) = (0, 0);
+        for (orig, reduced) in multitrie.t.tries.iter().zip(&reduced.t.tries) {
+            assert!(reduced.rows.len() <= orig.rows.len());
+            for key in stored_keys(orig) {
+                let expected = common_cmd(orig, &key);
+                if expected == orig.get_cmd(&key) {
+                    kept += 1;
+                } else {
+                    changed += 1;
+                }
+                assert_eq!(reduced.get_cmd(&key), expected, "{}", key);
+            }
+        }
+        assert!(kept > 0 && changed > 0);
+    }
+}
@@ -0,0 +1,143 @@
+use super::Stemmer;
+use crate::{Error, Stem, StemmerOptions};
+use std::borrow::Cow;
+use std::sync::{Arc, PoisonError, RwLock};
+use std::{fs, io, path::Path};
+
+/// A stemmer whose table can be replaced while it's in use, e.g. to pick up a retrained table in a long-running
+/// service.
+///
+/// Each call to `stem` uses the table which was current when it started, so replacing the table never waits for, or
+/// affects, calls in progress. A new table is only swapped in once it has been loaded and validated, so a failed
+/// reload leaves the current table in use.
+///
+/// ```rust,no_run
+/// use rstempel::{external::ReloadableStemmer, Stem};
+/// let stemmer = ReloadableStemmer::open("stemmer.tbl")?;
+/// println!("{}", stemmer.stem("kotami"));
+/// stemmer.reload_from_path("stemmer.tbl")?;
+/// # Ok::<(), rstempel::Error>(())
+/// ```
+pub struct ReloadableStemmer {
+    current: RwLock<Arc<Stemmer>>,
+}
+
+impl ReloadableStemmer {
+    pub fn new(stemmer: Stemmer) -> Self {
+        Self {
+            current: RwLock::new(Arc::new(stemmer)),
+        }
+    }
+
+    /// Loads the table in the file at `path` with [`Stemmer::load`].
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Ok(Self::new(load_path(path.as_ref())?))
+    }
+
+    /// Returns the current table, which stays usable after it's replaced.
+    pub fn current(&self) -> Arc<Stemmer> {
+        Arc::clone(&self.current.read().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    /// Makes `stemmer` the current table, returning the previous one.
+    pub fn replace(&self, stemmer: Stemmer) -> Arc<Stemmer> {
+        let mut current = self.current.write().unwrap_or_else(PoisonError::into_inner);
+        std::mem::replace(&mut *current, Arc::new(stemmer))
+    }
+
+    /// Loads a table with [`Stemmer::load`] and makes it the current table. If it fails to load, the current table is
+    /// kept.
+    pub fn reload<R: io::Read>(&self, reader: R) -> Result<(), Error> {
+        self.replace(Stemmer::load(reader)?);
+        Ok(())
+    }
+
+    /// Like [`ReloadableStemmer::reload`], loading the table in the file at `path`.
+    pub fn reload_from_path(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        self.replace(load_path(path.as_ref())?);
+        Ok(())
+    }
+}
+
+fn load_path(path: &Path) -> Result<Stemmer, Error> {
+    Stemmer::load(io::BufReader::new(fs::File::open(path)?))
+}
+
+impl Stem for ReloadableStemmer {
+    fn stem<'a>(&self, word: &'a str) -> Cow<'a, str> {
+        self.current().stem(word)
+    }
+
+    fn stem_into(&self, word: &str, out: &mut String) -> bool {
+        self.current().stem_into(word, out)
+    }
+
+    fn stem_into_with(&self, word: &str, out: &mut String, options: &StemmerOptions) -> bool {
+        self.current().stem_into_with(word, out, options)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::load_stemmer;
+    use std::{sync::atomic::AtomicBool, sync::atomic::Ordering, thread};
+
+    #[test]
+    fn test_reload() {
+        let stemmer = ReloadableStemmer::new(load_stemmer());
+        let old = stemmer.stem("ludzie").into_owned();
+        assert_ne!(old, "człowiek");
+
+        let mut patched = load_stemmer();
+        patched.add_pair("ludzie", "człowiek");
+        let mut bytes = Vec::new();
+        patched.save(&mut bytes).unwrap();
+
+        let previous = stemmer.current();
+        assert!(stemmer.reload(&bytes[..bytes.len() - 1]).is_err());
+        assert_eq!(stemmer.stem("ludzie"), old);
+
+        stemmer.reload(&bytes[..]).unwrap();
+        assert_eq!(stemmer.stem("ludzie"), "człowiek");
+        // Tables taken before the reload are unaffected.
+        assert_eq!(previous.stem("ludzie"), old);
+
+        let path = std::env::temp_dir().join(format!("rstempel-reload-{}.tbl", std::process::id()));
+        load_stemmer()
+            .save(fs::File::create(&path).unwrap())
+            .unwrap();
+        stemmer.reload_from_path(&path).unwrap();
+        assert_eq!(stemmer.stem("ludzie"), old);
+        assert_eq!(ReloadableStemmer::open(&path).unwrap().stem("ludzie"), old);
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(stemmer.reload_from_path(&path), Err(Error::Io(_))));
+    }
+
+    #[test]
+    fn test_reload_while_stemming() {
+        let stemmer = ReloadableStemmer::new(load_stemmer());
+        let old = stemmer.stem("ludzie").into_owned();
+        let mut patched = load_stemmer();
+        patched.add_pair("ludzie", "człowiek");
+        let mut bytes = Vec::new();
+        patched.save(&mut bytes).unwrap();
+
+        let done = AtomicBool::new(false);
+        thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    let mut seen_new = false;
+                    while !seen_new || !done.load(Ordering::Relaxed) {
+                        let stem = stemmer.stem("ludzie");
+                        seen_new = stem == "człowiek";
+                        assert!(seen_new || stem == old, "{}", stem);
+                        assert_eq!(stemmer.stem("kot"), "kot");
+                    }
+                });
+            }
+            stemmer.reload(&bytes[..]).unwrap();
+            done.store(true, Ordering::Relaxed);
+        });
+    }
+}
@@ -1,76 +1,191 @@
-use byteorder::{ReadBytesExt, BE};
+use crate::Error;
+use byteorder::{WriteBytesExt, BE};
 use std::io;
 
+/// The most items preallocated for a count read from a table. Corrupt tables can claim billions of items, so larger
+/// collections grow as their items are read instead.
+const MAX_PREALLOCATED: usize = 4096;
+
+/// Returns the capacity to preallocate for `count` items read from a table.
+pub(crate) fn capacity_for(count: usize) -> usize {
+    count.min(MAX_PREALLOCATED)
+}
+
 pub trait JavaDeserialize: Sized {
-    fn deserialize<R: io::Read>(reader: &mut DataInput<R>) -> io::Result<Self>;
+    fn deserialize<R: io::Read>(reader: &mut DataInput<R>) -> Result<Self, Error>;
+}
+
+pub trait JavaSerialize {
+    fn serialize<W: io::Write>(&self, writer: &mut DataOutput<W>) -> io::Result<()>;
 }
 
 /// Reads binary data in a manner compatible with
 /// [Java's DataInput class](https://docs.oracle.com/javase/7/docs/api/java/io/DataInput.html).
+///
+/// Errors include the byte offset of the value which couldn't be read.
 pub struct DataInput<R: io::Read> {
     inner: R,
+    offset: u64,
 }
 
 impl<R: io::Read> DataInput<R> {
     pub fn new(reader: R) -> Self {
-        Self { inner: reader }
+        Self {
+            inner: reader,
+            offset: 0,
+        }
+    }
+
+    /// Returns the number of bytes read so far, which is the offset of the next value.
+    pub(crate) fn offset(&self) -> u64 {
+        self.offset
     }
 
-    pub fn read<T: JavaDeserialize>(&mut self) -> io::Result<T> {
+    pub fn read<T: JavaDeserialize>(&mut self) -> Result<T, Error> {
         T::deserialize(self)
     }
 
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), Error> {
+        self.inner.read_exact(buf).map_err(|err| match err.kind() {
+            io::ErrorKind::UnexpectedEof => Error::Truncated {
+                offset: self.offset,
+            },
+            _ => Error::Io(err),
+        })?;
+        self.offset += buf.len() as u64;
+        Ok(())
+    }
+
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        let mut buf = [0; N];
+        self.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+
     /// Like Java's `readBoolean`.
-    pub fn read_bool(&mut self) -> io::Result<bool> {
-        Ok(self.inner.read_u8()? != 0)
+    pub fn read_bool(&mut self) -> Result<bool, Error> {
+        Ok(self.read_array::<1>()?[0] != 0)
     }
 
-    /// Like Java's `readInt` but casts the result to `u32`, returning [`std::io::ErrorKind::InvalidData`] if the value
-    /// is negative.
-    pub fn read_u32(&mut self) -> io::Result<u32> {
-        self.inner
-            .read_i32::<BE>()?
+    /// Like Java's `readInt` but casts the result to `u32`, returning [`Error::NegativeValue`] if the value is
+    /// negative.
+    pub fn read_u32(&mut self) -> Result<u32, Error> {
+        let offset = self.offset;
+        self.read_i32()?
             .try_into()
-            .map_err(|_| io::Error::from(io::ErrorKind::InvalidData))
+            .map_err(|_| Error::NegativeValue { offset })
     }
 
     /// Like Java's `readInt`
-    pub fn read_i32(&mut self) -> io::Result<i32> {
-        self.inner.read_i32::<BE>()
+    pub fn read_i32(&mut self) -> Result<i32, Error> {
+        Ok(i32::from_be_bytes(self.read_array()?))
     }
 
-    /// Like Java's `readInt` but casts the result to `usize`, returning [`std::io::ErrorKind::InvalidData`] if the value
-    /// is negative.
-    pub fn read_usize(&mut self) -> io::Result<usize> {
+    /// Like Java's `readInt` but casts the result to `usize`, returning [`Error::NegativeValue`] if the value is
+    /// negative.
+    pub fn read_usize(&mut self) -> Result<usize, Error> {
         Ok(self.read_u32()? as usize)
     }
 
     /// Like Java's `readInt` but casts the result to `u32`, returning [`Option::None`] if the value is negative.
-    pub fn read_u32_opt(&mut self) -> io::Result<Option<u32>> {
-        Ok(self.inner.read_i32::<BE>()?.try_into().ok())
+    pub fn read_u32_opt(&mut self) -> Result<Option<u32>, Error> {
+        Ok(self.read_i32()?.try_into().ok())
     }
 
     /// Like Java's `readChar`. Reads a UTF-16 code unit and converts it to a rust [`char`], returning
-    /// [`std::io::ErrorKind::InvalidData`] if the single UTF-16 code unit is not a valid UTF-16 code point.
-    pub fn read_char(&mut self) -> io::Result<char> {
-        let utf16_char = self.inner.read_u16::<BE>()?;
+    /// [`Error::InvalidChar`] if the single UTF-16 code unit is not a valid UTF-16 code point.
+    pub fn read_char(&mut self) -> Result<char, Error> {
+        let offset = self.offset;
+        let utf16_char = u16::from_be_bytes(self.read_array()?);
         char::decode_utf16(std::iter::once(utf16_char))
             .next()
             .unwrap()
-            .map_err(|_| io::Error::from(io::ErrorKind::InvalidData))
+            .map_err(|_| Error::InvalidChar { offset })
     }
 
-    /// Like Java's `readUTF`. Reads a modified UTF-8 string with length. Returns [`std::io::ErrorKind::InvalidData`]
-    /// if the value is not a valid UTF-8 string.
-    pub fn read_string(&mut self) -> io::Result<String> {
-        let len = self.inner.read_u16::<BE>()? as usize;
+    /// Like Java's `readUTF`. Reads a modified UTF-8 string with length. Returns [`Error::InvalidString`] if the
+    /// value is not a valid modified UTF-8 string.
+    pub fn read_string(&mut self) -> Result<String, Error> {
+        let offset = self.offset;
+        let len = u16::from_be_bytes(self.read_array()?) as usize;
         if len == 0 {
             return Ok(String::new());
         }
         let mut buf = vec![0u8; len];
-        self.inner.read_exact(&mut buf)?;
-        let str = cesu8::from_java_cesu8(&buf)
-            .map_err(|_| io::Error::from(io::ErrorKind::InvalidData))?;
+        self.read_exact(&mut buf)?;
+        let str = cesu8::from_java_cesu8(&buf).map_err(|_| Error::InvalidString { offset })?;
         Ok(str.into_owned())
     }
 }
+
+/// Writes binary data in a manner compatible with
+/// [Java's DataOutput class](https://docs.oracle.com/javase/7/docs/api/java/io/DataOutput.html).
+pub struct DataOutput<W: io::Write> {
+    inner: W,
+}
+
+impl<W: io::Write> DataOutput<W> {
+    pub fn new(writer: W) -> Self {
+        Self { inner: writer }
+    }
+
+    pub fn write<T: JavaSerialize>(&mut self, value: &T) -> io::Result<()> {
+        value.serialize(self)
+    }
+
+    /// Like Java's `writeBoolean`.
+    pub fn write_bool(&mut self, value: bool) -> io::Result<()> {
+        self.inner.write_u8(value.into())
+    }
+
+    /// Like Java's `writeInt`, returning [`std::io::ErrorKind::InvalidInput`] if the value doesn't fit in an `i32`.
+    pub fn write_u32(&mut self, value: u32) -> io::Result<()> {
+        let value = value
+            .try_into()
+            .map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+        self.write_i32(value)
+    }
+
+    /// Like Java's `writeInt`
+    pub fn write_i32(&mut self, value: i32) -> io::Result<()> {
+        self.inner.write_i32::<BE>(value)
+    }
+
+    /// Like Java's `writeInt`, returning [`std::io::ErrorKind::InvalidInput`] if the value doesn't fit in an `i32`.
+    pub fn write_usize(&mut self, value: usize) -> io::Result<()> {
+        let value = value
+            .try_into()
+            .map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+        self.write_i32(value)
+    }
+
+    /// Like Java's `writeInt`, writing [`Option::None`] as `-1`.
+    pub fn write_u32_opt(&mut self, value: Option<u32>) -> io::Result<()> {
+        match value {
+            Some(value) => self.write_u32(value),
+            None => self.write_i32(-1),
+        }
+    }
+
+    /// Like Java's `writeChar`. Writes a [`char`] as a single UTF-16 code unit, returning
+    /// [`std::io::ErrorKind::InvalidInput`] if it needs a surrogate pair.
+    pub fn write_char(&mut self, value: char) -> io::Result<()> {
+        let mut buf = [0u16; 2];
+        match *value.encode_utf16(&mut buf) {
+            [utf16_char] => self.inner.write_u16::<BE>(utf16_char),
+            _ => Err(io::Error::from(io::ErrorKind::InvalidInput)),
+        }
+    }
+
+    /// Like Java's `writeUTF`. Writes a modified UTF-8 string with length, returning
+    /// [`std::io::ErrorKind::InvalidInput`] if it is longer than 65535 bytes.
+    pub fn write_string(&mut self, value: &str) -> io::Result<()> {
+        let buf = cesu8::to_java_cesu8(value);
+        let len = buf
+            .len()
+            .try_into()
+            .map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+        self.inner.write_u16::<BE>(len)?;
+        self.inner.write_all(&buf)
+    }
+}
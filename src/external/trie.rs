@@ -52,14 +52,78 @@
 // created by Leo Galambos (Leo.G@seznam.cz).
 
 use super::serialize::*;
+use crate::command::InvalidPatch;
+use crate::explain::Trace;
+use crate::{Command, Error};
 use std::io;
-use std::{collections::BTreeMap, ops::Index};
+use std::{collections::BTreeMap, fmt, ops::Index};
 
 pub trait TrieGet {
     /// Return the command for the string key
     fn get_cmd(&self, key: &str) -> Option<String>;
 }
 
+/// The command stored in a cell of a trie, parsed from its patch string when the trie is loaded.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Patch {
+    /// The commands applied to the word.
+    Commands(Box<[Command]>),
+    /// The end marker `*` of a [`MultiTrie2`](super::multitrie::MultiTrie2), after which no more tries are used.
+    End,
+}
+
+impl Patch {
+    /// Parses a patch string.
+    pub(crate) fn parse(patch: &str) -> Result<Self, InvalidPatch> {
+        if patch == "*" {
+            Ok(Patch::End)
+        } else {
+            Ok(Patch::Commands(Command::parse_patch(patch)?.into()))
+        }
+    }
+
+    /// True if there are no commands, which leaves the word unchanged, and isn't stored.
+    fn is_empty(&self) -> bool {
+        matches!(self, Patch::Commands(commands) if commands.is_empty())
+    }
+
+    /// Returns the commands, which are empty for the end marker.
+    pub fn commands(&self) -> &[Command] {
+        match self {
+            Patch::Commands(commands) => commands,
+            Patch::End => &[],
+        }
+    }
+}
+
+impl From<&[Command]> for Patch {
+    fn from(commands: &[Command]) -> Self {
+        Patch::Commands(commands.into())
+    }
+}
+
+impl From<Vec<Command>> for Patch {
+    fn from(commands: Vec<Command>) -> Self {
+        Patch::Commands(commands.into())
+    }
+}
+
+/// Formats the patch string stored in the Java tables.
+impl fmt::Display for Patch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Patch::Commands(commands) => {
+                let mut patch = String::with_capacity(commands.len() * 2);
+                for cmd in commands.iter() {
+                    cmd.push_to(&mut patch);
+                }
+                f.write_str(&patch)
+            }
+            Patch::End => f.write_str("*"),
+        }
+    }
+}
+
 /// A Cell is a portion of a trie.
 #[derive(Default, Debug, Clone)]
 pub struct Cell {
@@ -74,7 +138,7 @@ pub struct Cell {
 }
 
 impl JavaDeserialize for Cell {
-    fn deserialize<R: io::Read>(reader: &mut DataInput<R>) -> io::Result<Self> {
+    fn deserialize<R: io::Read>(reader: &mut DataInput<R>) -> Result<Self, Error> {
         let cmd = reader.read_u32_opt()?;
         let cnt = reader.read_u32()?;
         let refr = reader.read_u32_opt()?;
@@ -88,12 +152,33 @@ impl JavaDeserialize for Cell {
     }
 }
 
+impl JavaSerialize for Cell {
+    fn serialize<W: io::Write>(&self, writer: &mut DataOutput<W>) -> io::Result<()> {
+        writer.write_u32_opt(self.cmd)?;
+        writer.write_u32(self.cnt)?;
+        writer.write_u32_opt(self.refr)?;
+        writer.write_u32(self.skip)
+    }
+}
+
 #[derive(Default, Debug, Clone)]
 pub struct Row {
     pub cells: BTreeMap<char, Cell>,
 }
 
 impl Row {
+    /// Sets the command for `way`, resetting its count to 1.
+    pub fn set_cmd(&mut self, way: char, cmd: u32) {
+        let cell = self.cells.entry(way).or_default();
+        cell.cmd = Some(cmd);
+        cell.cnt = 1;
+    }
+
+    /// Sets the next row for `way`.
+    pub fn set_ref(&mut self, way: char, refr: u32) {
+        self.cells.entry(way).or_default().refr = Some(refr);
+    }
+
     pub fn get_cmd(&self, way: char) -> Option<u32> {
         self.cells.get(&way)?.cmd
     }
@@ -112,7 +197,7 @@ impl Index<char> for Row {
 }
 
 impl JavaDeserialize for Row {
-    fn deserialize<R: io::Read>(reader: &mut DataInput<R>) -> io::Result<Self> {
+    fn deserialize<R: io::Read>(reader: &mut DataInput<R>) -> Result<Self, Error> {
         let num = reader.read_usize()?;
         let mut cells = BTreeMap::new();
 
@@ -126,10 +211,26 @@ impl JavaDeserialize for Row {
     }
 }
 
+impl JavaSerialize for Row {
+    fn serialize<W: io::Write>(&self, writer: &mut DataOutput<W>) -> io::Result<()> {
+        // Like the Java implementation we skip cells without a command or reference, but we don't count them either.
+        let cells = self
+            .cells
+            .iter()
+            .filter(|(_, cell)| cell.cmd.is_some() || cell.refr.is_some());
+        writer.write_usize(cells.clone().count())?;
+        for (&ch, cell) in cells {
+            writer.write_char(ch)?;
+            writer.write(cell)?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Default, Debug, Clone)]
 pub struct Trie {
     pub(crate) rows: Vec<Row>,
-    pub(crate) cmds: Vec<String>,
+    pub(crate) cmds: Vec<Patch>,
     pub(crate) root: u32,
     pub(crate) forward: bool,
 }
@@ -137,50 +238,214 @@ pub struct Trie {
 // TODO: looks like rows and cmds are basically arenas and we're using indexes into
 // them as handles. Should we switch to an arena or a trie crate or make a handle type or...?
 impl Trie {
+    /// Creates an empty trie which reads keys from the start if `forward`, or from the end otherwise.
+    pub fn new(forward: bool) -> Self {
+        Self {
+            rows: vec![Row::default()],
+            cmds: Vec::new(),
+            root: 0,
+            forward,
+        }
+    }
+
     pub fn row(&self, index: u32) -> Option<&Row> {
         self.rows.get(index as usize)
     }
+
+    /// Stores `cmd` as the command for `key`, adding rows for any part of the path which doesn't exist yet.
+    pub fn add(&mut self, key: &str, cmd: impl Into<Patch>) {
+        let cmd = cmd.into();
+        if key.is_empty() || cmd.is_empty() {
+            return;
+        }
+        let cmd = self.cmd_index(cmd);
+        let mut node = self.root as usize;
+        let mut chars = KeyIter::new(self.forward, key);
+        let last_ch = chars.next_back().unwrap();
+        for ch in chars {
+            node = match self.rows[node].get_ref(ch) {
+                Some(next) => next as usize,
+                None => {
+                    let next = self.rows.len();
+                    self.rows.push(Row::default());
+                    self.rows[node].set_ref(ch, next as u32);
+                    next
+                }
+            };
+        }
+        self.rows[node].set_cmd(last_ch, cmd);
+    }
+
+    /// Like [`Trie::add`], but copies every row along the path of `key` instead of modifying it. Rows of optimized
+    /// tries can be shared between many keys, so this only changes the command found for keys which contain `key`
+    /// (at their end, or at their start for forward tries). The replaced rows are left in place until they're removed
+    /// by [`Reduce::RemoveGaps`](super::reduce::Reduce::RemoveGaps).
+    pub fn patch(&mut self, key: &str, cmd: impl Into<Patch>) {
+        let cmd = cmd.into();
+        if key.is_empty() || cmd.is_empty() {
+            return;
+        }
+        let cmd = self.cmd_index(cmd);
+        let mut node = self.copy_row(Some(self.root));
+        self.root = node;
+        let mut chars = KeyIter::new(self.forward, key);
+        let last_ch = chars.next_back().unwrap();
+        for ch in chars {
+            let next = self.copy_row(self.rows[node as usize].get_ref(ch));
+            self.rows[node as usize].set_ref(ch, next);
+            node = next;
+        }
+        self.rows[node as usize].set_cmd(last_ch, cmd);
+    }
+
+    /// Appends a copy of the row, or an empty row, returning its index.
+    fn copy_row(&mut self, index: Option<u32>) -> u32 {
+        let row = index.map_or_else(Row::default, |idx| self.rows[idx as usize].clone());
+        self.rows.push(row);
+        (self.rows.len() - 1) as u32
+    }
+
+    /// Checks that the root and every row and command reference are valid, so that using the trie can't panic. Loading
+    /// a [`Stemmer`](super::Stemmer) does this already, after checking every command while reading it.
+    pub fn validate(&self) -> Result<(), Error> {
+        self.validate_in(0)
+    }
+
+    /// Like [`Trie::validate`], reporting errors in the trie with index `trie`.
+    pub(crate) fn validate_in(&self, trie: usize) -> Result<(), Error> {
+        if self.root as usize >= self.rows.len() {
+            return Err(Error::InvalidRoot {
+                trie,
+                root: self.root,
+            });
+        }
+        for (row_idx, row) in self.rows.iter().enumerate() {
+            for (&cell, value) in &row.cells {
+                if value.refr.is_some_and(|r| r as usize >= self.rows.len()) {
+                    return Err(Error::InvalidRowReference {
+                        trie,
+                        row: row_idx,
+                        cell,
+                    });
+                }
+                if value.cmd.is_some_and(|c| c as usize >= self.cmds.len()) {
+                    return Err(Error::InvalidCommandIndex {
+                        trie,
+                        row: row_idx,
+                        cell,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn cmd_index(&mut self, cmd: Patch) -> u32 {
+        match self.cmds.iter().position(|c| *c == cmd) {
+            Some(idx) => idx as u32,
+            None => {
+                self.cmds.push(cmd);
+                (self.cmds.len() - 1) as u32
+            }
+        }
+    }
 }
 
-impl TrieGet for Trie {
-    fn get_cmd(&self, key: &str) -> Option<String> {
-        let mut now = self.row(self.root)?;
+impl Trie {
+    /// Like [`TrieGet::get_cmd`], but borrows the parsed command.
+    pub fn get_cmd_ref(&self, key: &str) -> Option<&Patch> {
+        self.get_cmd_traced(key, &mut ())
+    }
+
+    /// Like [`Trie::get_cmd_ref`], but passes each cell on the path of `key` to `trace`.
+    pub(crate) fn get_cmd_traced<'a>(
+        &'a self,
+        key: &str,
+        trace: &mut impl Trace<&'a [Command]>,
+    ) -> Option<&'a Patch> {
+        let mut idx = self.root;
+        let mut now = self.row(idx)?;
         let mut chars = KeyIter::new(self.forward, key);
         let mut last = None;
-        let last_ch = chars.next_back().unwrap();
+        let last_ch = chars.next_back()?;
         for ch in chars {
-            if let Some(idx) = now.get_cmd(ch) {
-                last = self.cmds.get(idx as usize);
+            if let Some(cell) = now.cells.get(&ch) {
+                trace.step(idx as usize, ch, cell.cmd.is_some());
+            }
+            if let Some(cmd) = now.get_cmd(ch) {
+                last = self.cmds.get(cmd as usize);
             }
-            if let Some(idx) = now.get_ref(ch) {
+            if let Some(next) = now.get_ref(ch) {
+                idx = next;
                 now = self.row(idx)?;
             } else {
-                return last.cloned();
+                return last;
             }
         }
-        if let Some(idx) = now.get_cmd(last_ch) {
-            self.cmds.get(idx as usize)
+        if let Some(cell) = now.cells.get(&last_ch) {
+            trace.step(idx as usize, last_ch, cell.cmd.is_some());
+        }
+        if let Some(cmd) = now.get_cmd(last_ch) {
+            self.cmds.get(cmd as usize)
         } else {
             last
         }
-        .cloned()
+    }
+}
+
+impl TrieGet for Trie {
+    fn get_cmd(&self, key: &str) -> Option<String> {
+        self.get_cmd_ref(key).map(Patch::to_string)
     }
 }
 
 impl JavaDeserialize for Trie {
-    fn deserialize<R: io::Read>(reader: &mut DataInput<R>) -> io::Result<Self> {
+    fn deserialize<R: io::Read>(reader: &mut DataInput<R>) -> Result<Self, Error> {
+        Self::deserialize_in(reader, 0)
+    }
+}
+
+impl Trie {
+    /// Like [`JavaDeserialize::deserialize`], reporting invalid commands in the trie with index `trie`.
+    pub(crate) fn deserialize_in<R: io::Read>(
+        reader: &mut DataInput<R>,
+        trie: usize,
+    ) -> Result<Self, Error> {
         let forward = reader.read_bool()?;
         let root = reader.read_u32()?;
         let num_cmds = reader.read_usize()?;
-        let mut cmds = Vec::with_capacity(num_cmds);
-        for _ in 0..num_cmds {
-            cmds.push(reader.read_string()?);
+        let mut cmds = Vec::with_capacity(capacity_for(num_cmds));
+        let mut invalid = None;
+        for index in 0..num_cmds {
+            let offset = reader.offset();
+            let command = reader.read_string()?;
+            match Patch::parse(&command) {
+                Ok(patch) => cmds.push(patch),
+                // Keep reading, so that the error can name a cell which refers to the command.
+                Err(err) => {
+                    invalid.get_or_insert((err, index, offset, command));
+                }
+            }
         }
-        let num_rows = reader.read_usize()?;
-        let mut rows = Vec::with_capacity(num_rows);
-        for _ in 0..num_rows {
-            rows.push(Row::deserialize(reader)?);
+        let rows = reader.read_usize().and_then(|num_rows| {
+            let mut rows = Vec::with_capacity(capacity_for(num_rows));
+            for _ in 0..num_rows {
+                rows.push(Row::deserialize(reader)?);
+            }
+            Ok(rows)
+        });
+        if let Some((err, index, offset, command)) = invalid {
+            let cell = rows.as_deref().ok().and_then(|rows| {
+                rows.iter().enumerate().find_map(|(idx, row)| {
+                    row.cells
+                        .iter()
+                        .find(|(_, cell)| cell.cmd == Some(index as u32))
+                        .map(|(&ch, _)| (idx, ch))
+                })
+            });
+            return Err(err.at(trie, index, offset, cell, command));
         }
+        let rows = rows?;
         Ok(Self {
             forward,
             root,
@@ -190,6 +455,22 @@ impl JavaDeserialize for Trie {
     }
 }
 
+impl JavaSerialize for Trie {
+    fn serialize<W: io::Write>(&self, writer: &mut DataOutput<W>) -> io::Result<()> {
+        writer.write_bool(self.forward)?;
+        writer.write_u32(self.root)?;
+        writer.write_usize(self.cmds.len())?;
+        for cmd in &self.cmds {
+            writer.write_string(&cmd.to_string())?;
+        }
+        writer.write_usize(self.rows.len())?;
+        for row in &self.rows {
+            writer.write(row)?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Clone)]
 struct KeyIter<'a> {
     inner: std::str::Chars<'a>,
@@ -19,8 +19,29 @@
 //! ## Unicode Normalization
 //!
 //! It is recommended to Unicode normalize (NFC) the input before stemming, as combining diacritical marks are not
-//! handled correctly on their own. The [unicode-normalization crate](https://github.com/unicode-rs/unicode-normalization)
-//! can be used for this.
+//! handled correctly on their own. The `normalize` feature does this for every stemmer, using the
+//! [unicode-normalization crate](https://github.com/unicode-rs/unicode-normalization), so that e.g. a decomposed `ą`
+//! (`a` followed by U+0328) stems like the precomposed letter. Text which only contains ASCII and Polish letters is
+//! already normalized, and is passed through without any extra work.
+//!
+//! ## Case
+//!
+//! The stemming tables are case-sensitive, and mostly contain lowercase words. `IgnoreCase` wraps a stemmer to stem
+//! words in all caps or title case like their lowercase forms, keeping their casing, so that `KOTAMI`, `Kotami` and
+//! `kotami` are stemmed alike.
+//!
+//! ## Tokenizing
+//!
+//! `rstempel::tokenize` splits text into words with their byte offsets, which can be passed straight to a stemmer.
+//! Numbers, URLs, e-mail addresses and punctuation are kept as tokens of their own kind, which aren't meant to be
+//! stemmed. `Stem::stem_text` does both, returning each token of a text with its offsets, position and stem.
+//!
+//! `Analyzer` turns text into search terms with the same steps as Lucene's `PolishAnalyzer`: tokens are lowercased,
+//! stop words are removed and the remaining words are stemmed, with optional NFC normalization and `Overrides`. The
+//! `stop_words` feature embeds the Polish stop words shipped with Lucene as `StopWords::polish()`.
+//!
+//! `Compounds` wraps a stemmer to stem the parts of hyphenated compounds like `polsko-niemiecki` on their own, or only
+//! their last part. `AnalyzerBuilder::compounds` does the same for an `Analyzer`.
 //!
 //! ## Stemmer Implementations
 //!
@@ -36,18 +57,242 @@
 //! table from the stempel stemmer project is included in `src/tables/stemmer_2000.out.gz`. A much larger and more
 //! accurate stemming table can be sourced from [pystempel](https://github.com/dzieciou/pystempel).
 //!
+//! The `binary` feature enables `rstempel::binary`, which reads tables converted to a compact binary format directly
+//! from a byte slice, such as a memory-mapped file, without allocating. This avoids the startup cost of loading
+//! `external` tables. `examples/generate.rs` also writes each table in this format, which can be embedded with
+//! `include_bytes!` to avoid compiling large tables as Rust code. The `table_2000_bin` feature embeds the same table as
+//! `table_2000` this way, as `rstempel::binary::STEMMER`.
+//!
+//! ## Training
+//!
+//! The `train` feature enables `rstempel::train`, a port of the Java `Compile` tool which builds `external` stemming
+//! tables from inflected words and their stems, without needing a JVM.
+//!
 //! ## Acknowledgements
 //!
 //! This product includes software developed by the Egothor Project. http://egothor.sf.net/
 
+#[cfg(any(feature = "external", feature = "embedded"))]
+mod command;
+#[cfg(any(feature = "external", feature = "embedded"))]
+pub use command::Command;
+#[cfg(any(feature = "external", feature = "embedded"))]
+mod edit;
+mod error;
+pub use error::Error;
+mod case;
+pub use case::IgnoreCase;
+mod normalize;
+mod options;
+pub use options::{StemmerOptions, WithOptions};
+mod overrides;
+pub use overrides::Overrides;
+mod stop_words;
+pub mod tokenize;
+pub use stop_words::StopWords;
+mod compound;
+pub use compound::{CompoundParts, Compounds};
+mod analyzer;
+pub use analyzer::{Analyzer, AnalyzerBuilder, Terms};
+
+#[cfg(any(feature = "external", feature = "embedded"))]
+pub mod explain;
+
 #[cfg(feature = "external")]
 pub mod external;
 
 #[cfg(feature = "embedded")]
 pub mod embedded;
 
+#[cfg(feature = "binary")]
+pub mod binary;
+
+#[cfg(feature = "train")]
+pub mod train;
+
+#[cfg(test)]
+mod test_util;
+
 pub trait Stem {
     /// If the stemmed word is unchanged, returns `Cow::Borrowed(word)`,
     /// else returns `Cow::Owned` with the stemmed word.
     fn stem<'a>(&self, word: &'a str) -> std::borrow::Cow<'a, str>;
+
+    /// Replaces the contents of `out` with the stemmed word, returning `true` if it differs from `word`.
+    ///
+    /// The stemmers in this crate don't allocate, except to grow `out`, so reusing it avoids allocating for each word.
+    /// See also [`StemBuffer`].
+    fn stem_into(&self, word: &str, out: &mut String) -> bool {
+        let stem = self.stem(word);
+        out.clear();
+        out.push_str(&stem);
+        matches!(stem, std::borrow::Cow::Owned(_))
+    }
+
+    /// Like [`Stem::stem_into`], but with the lengths and number of stages set by `options`. See also
+    /// [`StemmerOptions::wrap`].
+    ///
+    /// The stemmers in this crate honour every option. This default implementation can't limit the number of stages,
+    /// or stem words which [`Stem::stem_into`] leaves unchanged for being too short.
+    fn stem_into_with(&self, word: &str, out: &mut String, options: &StemmerOptions) -> bool {
+        let changed = !options.is_too_short(word) && self.stem_into(word, out);
+        if !changed || options.is_stem_too_short(out) {
+            out.clear();
+            out.push_str(word);
+            return false;
+        }
+        true
+    }
+
+    /// Splits `text` into tokens with [`tokenize`](tokenize::tokenize) and stems its words, returning every token
+    /// except punctuation with its byte offsets and position. Stems which are unchanged are borrowed from `text`.
+    ///
+    /// ```rust
+    /// use rstempel::Stem;
+    /// for token in rstempel::embedded::STEMMER.stem_text("Kot pije mleko.") {
+    ///     println!("{:?} {}: {}", token.range(), token.text, token.stem);
+    /// }
+    /// ```
+    fn stem_text<'t>(&self, text: &'t str) -> tokenize::StemmedTokens<'_, 't, Self>
+    where
+        Self: Sized,
+    {
+        tokenize::StemmedTokens::new(self, text)
+    }
+}
+
+/// Implements [`Stem`] for pointers to stemmers, so that e.g. a stemmer shared between threads in an `Arc` can be used,
+/// or wrapped, like the stemmer itself.
+macro_rules! impl_stem_for_pointer {
+    ($($pointer:ty),*) => {$(
+        impl<S: Stem + ?Sized> Stem for $pointer {
+            fn stem<'a>(&self, word: &'a str) -> std::borrow::Cow<'a, str> {
+                (**self).stem(word)
+            }
+
+            fn stem_into(&self, word: &str, out: &mut String) -> bool {
+                (**self).stem_into(word, out)
+            }
+
+            fn stem_into_with(&self, word: &str, out: &mut String, options: &StemmerOptions) -> bool {
+                (**self).stem_into_with(word, out, options)
+            }
+        }
+    )*};
+}
+
+impl_stem_for_pointer!(&S, Box<S>, std::sync::Arc<S>);
+
+/// A reusable buffer for stemming many words without allocating, once it has grown to fit the longest stem.
+///
+/// ```rust
+/// use rstempel::{Stem, StemBuffer};
+/// let mut buffer = StemBuffer::new();
+/// for word in ["kotami", "książkami"] {
+///     let stem: &str = buffer.stem(&rstempel::embedded::STEMMER, word);
+/// #   assert!(!stem.is_empty());
+/// }
+/// ```
+#[derive(Default, Debug, Clone)]
+pub struct StemBuffer {
+    buf: String,
+}
+
+impl StemBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stems `word` with [`Stem::stem_into`] and returns the stem, which is borrowed until the next call.
+    pub fn stem<S: Stem + ?Sized>(&mut self, stemmer: &S, word: &str) -> &str {
+        stemmer.stem_into(word, &mut self.buf);
+        &self.buf
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::Suffix;
+    use std::{borrow::Cow, sync::Arc};
+
+    #[test]
+    fn test_pointers() {
+        fn stem(stemmer: impl Stem) -> String {
+            stemmer.stem("kot").into_owned()
+        }
+
+        assert_eq!(stem(&Suffix), "kotx");
+        assert_eq!(stem(Box::new(Suffix)), "kotx");
+        assert_eq!(stem(Arc::new(Suffix)), "kotx");
+        let boxed: Box<dyn Stem + Send + Sync> = Box::new(Suffix);
+        assert_eq!(stem(&boxed), "kotx");
+        let shared: Arc<dyn Stem + Send + Sync> = Arc::new(IgnoreCase::new(Suffix));
+        assert_eq!(stem(Arc::clone(&shared)), "kotx");
+        assert_eq!(StemBuffer::new().stem(&shared, "KOTA"), "KOTAX");
+    }
+
+    #[test]
+    fn test_stem_text() {
+        use tokenize::TokenKind;
+
+        let text = "Kot, 2 psy i żółw.";
+        let tokens: Vec<_> = Suffix.stem_text(text).collect();
+        let stems: Vec<_> = tokens
+            .iter()
+            .map(|token| (token.position, &text[token.range()], &*token.stem))
+            .collect();
+        assert_eq!(
+            stems,
+            [
+                (0, "Kot", "Kotx"),
+                (1, "2", "2"),
+                (2, "psy", "psyx"),
+                (3, "i", "ix"),
+                (4, "żółw", "żółwx"),
+            ]
+        );
+        assert_eq!((tokens[4].start, tokens[4].end), (13, 20));
+        assert_eq!(tokens[1].kind, TokenKind::Number);
+        assert!(matches!(tokens[1].stem, Cow::Borrowed("2")));
+
+        let boxed: Box<dyn Stem> = Box::new(IgnoreCase::new(Suffix));
+        assert_eq!(boxed.stem_text("KOT").next().unwrap().stem, "KOTX");
+        assert!(Suffix.stem_text(" .,").next().is_none());
+    }
+
+    #[cfg(feature = "table_2000")]
+    #[test]
+    fn test_stem_text_borrows_unchanged_words() {
+        use embedded::STEMMER;
+
+        let tokens: Vec<_> = STEMMER.stem_text("Kot i psy czytałem kotek.").collect();
+        assert_eq!(tokens.len(), 5);
+        for token in &tokens {
+            // Short words and words the table leaves unchanged are borrowed from the text, without allocating.
+            let borrowed = matches!(token.stem, Cow::Borrowed(_));
+            assert_eq!(borrowed, token.stem == token.text, "{}", token.text);
+        }
+        assert!(matches!(tokens[0].stem, Cow::Borrowed("Kot")));
+        assert!(matches!(tokens[2].stem, Cow::Borrowed("psy")));
+        assert!(matches!(tokens[3].stem, Cow::Owned(_)));
+        assert!(matches!(tokens[4].stem, Cow::Borrowed("kotek")));
+    }
+
+    #[test]
+    fn test_send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+
+        #[cfg(feature = "external")]
+        assert_send_sync::<external::Stemmer>();
+        #[cfg(feature = "embedded")]
+        assert_send_sync::<embedded::Stemmer>();
+        #[cfg(feature = "binary")]
+        assert_send_sync::<binary::Stemmer>();
+        assert_send_sync::<WithOptions<Arc<Suffix>>>();
+        assert_send_sync::<IgnoreCase<Arc<Suffix>>>();
+        assert_send_sync::<Overrides<Arc<Suffix>>>();
+        assert_send_sync::<Analyzer<Arc<Suffix>>>();
+        assert_send_sync::<Compounds<Arc<Suffix>>>();
+    }
 }
@@ -0,0 +1,75 @@
+//! Normalizes words to NFC before stemming, with the `normalize` feature. The tables are made of precomposed letters,
+//! and each combining mark would otherwise count as a char of its own.
+
+use std::borrow::Cow;
+
+/// Returns `word` in NFC, borrowing it if it's already normalized. Without the `normalize` feature, always returns
+/// `word` unchanged.
+#[cfg(feature = "normalize")]
+pub(crate) fn nfc(word: &str) -> Cow<'_, str> {
+    use unicode_normalization::{is_nfc_quick, IsNormalized, UnicodeNormalization};
+
+    // Chars below U+0300, which include ASCII and every Polish letter, are all in NFC and can't be composed with what
+    // precedes them. In UTF-8 these are exactly the chars whose bytes are all below 0xCC.
+    if word.bytes().all(|b| b < 0xCC) || is_nfc_quick(word.chars()) == IsNormalized::Yes {
+        return Cow::Borrowed(word);
+    }
+    let normalized: String = word.nfc().collect();
+    if normalized == word {
+        Cow::Borrowed(word)
+    } else {
+        Cow::Owned(normalized)
+    }
+}
+
+#[cfg(not(feature = "normalize"))]
+pub(crate) fn nfc(word: &str) -> Cow<'_, str> {
+    Cow::Borrowed(word)
+}
+
+#[cfg(all(test, feature = "normalize"))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_nfc() {
+        assert!(matches!(nfc("zażółć gęślą jaźń"), Cow::Borrowed(_)));
+        assert!(matches!(nfc("ZAŻÓŁĆ GĘŚLĄ JAŹŃ"), Cow::Borrowed(_)));
+        assert!(matches!(nfc("日本語"), Cow::Borrowed(_)));
+        assert_eq!(nfc("ka\u{328}ta"), "kąta");
+        assert_eq!(nfc("Z\u{307}O\u{301}ŁW"), "ŻÓŁW");
+        // A combining mark which has no precomposed form with its base is kept.
+        assert_eq!(nfc("x\u{328}"), "x\u{328}");
+    }
+
+    #[cfg(feature = "table_2000")]
+    #[test]
+    fn test_decomposed_input() {
+        use crate::embedded::STEMMER;
+        use crate::Stem;
+
+        for (decomposed, precomposed) in [
+            ("ksia\u{328}z\u{307}kami", "książkami"),
+            ("z\u{307}o\u{301}łwiami", "żółwiami"),
+            ("robilis\u{301}my", "robiliśmy"),
+        ] {
+            assert_eq!(STEMMER.stem(decomposed), STEMMER.stem(precomposed));
+            assert_eq!(
+                STEMMER.stem_explain(decomposed),
+                STEMMER.stem_explain(precomposed)
+            );
+        }
+        // Unchanged words are still returned in NFC.
+        assert_eq!(STEMMER.stem("ka\u{328}t"), "kąt");
+
+        #[cfg(feature = "external")]
+        {
+            let external = crate::test_util::load_stemmer();
+            assert_eq!(
+                external.stem("ksia\u{328}z\u{307}kami"),
+                external.stem("książkami")
+            );
+            assert_eq!(external.stem("ka\u{328}t"), "kąt");
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use crate::Stem;
+use std::borrow::Cow;
+
+/// Options for how a stemmer is applied, which can make stemming lighter or more conservative than the defaults.
+///
+/// ```rust
+/// use rstempel::{Stem, StemmerOptions};
+/// let stemmer = StemmerOptions::new()
+///     .min_length(5)
+///     .min_stem_length(3)
+///     .wrap(&rstempel::embedded::STEMMER);
+/// assert_eq!(stemmer.stem("kota"), "kota");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StemmerOptions {
+    pub(crate) min_length: usize,
+    pub(crate) max_stages: usize,
+    pub(crate) min_stem_length: usize,
+}
+
+impl StemmerOptions {
+    /// Returns the options used by [`Stem::stem`]: words of 3 or fewer chars are left unchanged, every trie is used,
+    /// and any non-empty stem is accepted.
+    pub const fn new() -> Self {
+        Self {
+            min_length: 4,
+            max_stages: usize::MAX,
+            min_stem_length: 1,
+        }
+    }
+
+    /// Words with fewer than `chars` chars are left unchanged.
+    pub const fn min_length(mut self, chars: usize) -> Self {
+        self.min_length = chars;
+        self
+    }
+
+    /// Uses at most the first `stages` tries of a multi-trie table, for lighter stemming. Each trie removes or
+    /// rewrites another part of the word's ending. A table with a single trie is used if `stages` isn't 0.
+    pub const fn max_stages(mut self, stages: usize) -> Self {
+        self.max_stages = stages;
+        self
+    }
+
+    /// Words are left unchanged if their stem would have fewer than `chars` chars. Stems are never empty.
+    pub const fn min_stem_length(mut self, chars: usize) -> Self {
+        self.min_stem_length = chars;
+        self
+    }
+
+    /// Returns a stemmer which applies these options to `stemmer`.
+    pub fn wrap<S: Stem>(self, stemmer: S) -> WithOptions<S> {
+        WithOptions {
+            stemmer,
+            options: self,
+        }
+    }
+
+    /// True if `word` is too short to be stemmed.
+    pub(crate) fn is_too_short(&self, word: &str) -> bool {
+        word.chars().count() < self.min_length
+    }
+
+    /// True if `stem` is too short to be used.
+    pub(crate) fn is_stem_too_short(&self, stem: &str) -> bool {
+        stem.is_empty() || stem.chars().count() < self.min_stem_length
+    }
+}
+
+impl Default for StemmerOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A stemmer which applies [`StemmerOptions`], returned by [`StemmerOptions::wrap`].
+#[derive(Debug, Clone)]
+pub struct WithOptions<S> {
+    stemmer: S,
+    options: StemmerOptions,
+}
+
+impl<S> WithOptions<S> {
+    pub fn options(&self) -> &StemmerOptions {
+        &self.options
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.stemmer
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stemmer
+    }
+}
+
+impl<S: Stem> Stem for WithOptions<S> {
+    fn stem<'a>(&self, word: &'a str) -> Cow<'a, str> {
+        let mut out = String::new();
+        if self.stem_into(word, &mut out) {
+            Cow::Owned(out)
+        } else {
+            Cow::Borrowed(word)
+        }
+    }
+
+    fn stem_into(&self, word: &str, out: &mut String) -> bool {
+        self.stemmer.stem_into_with(word, out, &self.options)
+    }
+}
+
+#[cfg(all(test, feature = "table_2000"))]
+mod test {
+    use super::*;
+    use crate::embedded::STEMMER;
+
+    const WORDS: &[&str] = &[
+        "kotami",
+        "książkami",
+        "czytałem",
+        "robiliśmy",
+        "ludziom",
+        "pięknymi",
+        "kot",
+        "zdzie",
+    ];
+
+    #[test]
+    fn test_default_options() {
+        let stemmer = StemmerOptions::default().wrap(&STEMMER);
+        for word in WORDS {
+            assert_eq!(stemmer.stem(word), STEMMER.stem(word));
+        }
+    }
+
+    #[test]
+    fn test_lengths() {
+        let stemmer = StemmerOptions::new().min_length(7).wrap(&STEMMER);
+        assert_eq!(stemmer.stem("kotami"), "kotami");
+        assert_eq!(stemmer.stem("książkami"), STEMMER.stem("książkami"));
+
+        let stemmer = StemmerOptions::new().min_stem_length(6).wrap(&STEMMER);
+        for word in WORDS {
+            let stem = STEMMER.stem(word);
+            let expected: &str = if stem.chars().count() < 6 {
+                word
+            } else {
+                &stem
+            };
+            assert_eq!(stemmer.stem(word), expected);
+        }
+    }
+
+    #[test]
+    fn test_max_stages() {
+        let none = StemmerOptions::new().max_stages(0).wrap(&STEMMER);
+        let all = StemmerOptions::new().max_stages(usize::MAX).wrap(&STEMMER);
+        for word in WORDS {
+            assert_eq!(none.stem(word), *word);
+            assert_eq!(all.stem(word), STEMMER.stem(word));
+        }
+        // The stages used for each word by the full stemmer give the same stem when limiting to that many.
+        for word in WORDS {
+            let used = STEMMER
+                .stem_explain(word)
+                .stages
+                .iter()
+                .filter(|stage| stage.decision == crate::explain::Decision::Used)
+                .count();
+            let limited = StemmerOptions::new().max_stages(used).wrap(&STEMMER);
+            assert_eq!(limited.stem(word), STEMMER.stem(word));
+        }
+    }
+}
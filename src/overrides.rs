@@ -0,0 +1,244 @@
+use crate::case::{self, Casing};
+use crate::{normalize, Error, Stem, StemmerOptions};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io;
+
+/// A stemmer which looks words up in a map of exact stems, and a set of protected words which are never stemmed,
+/// before falling back to another stemmer. Use this to fix words which the stemming table gets wrong, such as
+/// irregular forms.
+///
+/// ```rust
+/// use rstempel::{Overrides, Stem};
+/// let mut stemmer = Overrides::new(&rstempel::embedded::STEMMER).ignore_case();
+/// stemmer.insert("ludzie", "człowiek");
+/// stemmer.protect("Kraków");
+/// assert_eq!(stemmer.stem("Ludzie"), "Człowiek");
+/// assert_eq!(stemmer.stem("kraków"), "kraków");
+/// ```
+#[derive(Debug, Clone)]
+pub struct Overrides<S> {
+    stemmer: S,
+    overrides: HashMap<String, Override>,
+    /// The number of overrides added so far, to order them.
+    added: usize,
+    ignore_case: bool,
+}
+
+/// The override for a word.
+#[derive(Debug, Clone)]
+struct Override {
+    /// The stem of the word, or `None` if it's protected.
+    stem: Option<String>,
+    /// When the override was added, counting from zero.
+    added: usize,
+}
+
+impl<S> Overrides<S> {
+    pub fn new(stemmer: S) -> Self {
+        Self {
+            stemmer,
+            overrides: HashMap::new(),
+            added: 0,
+            ignore_case: false,
+        }
+    }
+
+    /// Matches words in lowercase, including the overrides added so far. Stems of words in all caps or title case are
+    /// given the casing of the word, like [`IgnoreCase`](crate::IgnoreCase). If several overrides were added for words
+    /// which are the same in lowercase, such as `Kraków` and `KRAKÓW`, the one added last is kept, as if they had been
+    /// added after this.
+    pub fn ignore_case(mut self) -> Self {
+        self.ignore_case = true;
+        let mut overrides: Vec<_> = self.overrides.drain().collect();
+        overrides.sort_unstable_by_key(|(_, o)| o.added);
+        self.overrides = overrides
+            .into_iter()
+            .map(|(word, o)| (case::lowercase(&word), o))
+            .collect();
+        self
+    }
+
+    /// Stems `word` as `stem`, replacing any previous override for it.
+    pub fn insert(&mut self, word: &str, stem: impl Into<String>) {
+        self.add(word, Some(stem.into()));
+    }
+
+    /// Leaves `word` unchanged, replacing any previous override for it.
+    pub fn protect(&mut self, word: &str) {
+        self.add(word, None);
+    }
+
+    fn add(&mut self, word: &str, stem: Option<String>) {
+        let key = self.key(word).into_owned();
+        let added = self.added;
+        self.added += 1;
+        self.overrides.insert(key, Override { stem, added });
+    }
+
+    /// Adds the overrides in a file of tab separated values. Each line holds a word and its stem separated by a tab,
+    /// or a single word to protect. Empty lines, and lines starting with `#`, are skipped.
+    pub fn load_tsv<R: io::BufRead>(&mut self, reader: R) -> Result<(), Error> {
+        for (idx, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim_end_matches('\r');
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut fields = line.split('\t');
+            match (fields.next(), fields.next(), fields.next()) {
+                (Some(word), None, None) => self.protect(word),
+                (Some(word), Some(stem), None) if !word.is_empty() && !stem.is_empty() => {
+                    self.insert(word, stem)
+                }
+                _ => return Err(Error::InvalidOverride { line: idx + 1 }),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.stemmer
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stemmer
+    }
+
+    fn key<'a>(&self, word: &'a str) -> Cow<'a, str> {
+        let word = normalize::nfc(word);
+        if self.ignore_case {
+            Cow::Owned(case::lowercase(&word))
+        } else {
+            word
+        }
+    }
+
+    /// Writes the override for `word` to `out`, returning `None` if it has none.
+    pub(crate) fn lookup(&self, word: &str, out: &mut String) -> Option<bool> {
+        let key = self.key(word);
+        let stem = match &self.overrides.get(&*key)?.stem {
+            Some(stem) => stem,
+            None => {
+                out.clear();
+                out.push_str(word);
+                return Some(false);
+            }
+        };
+        out.clear();
+        out.push_str(stem);
+        if self.ignore_case {
+            Casing::of(word).apply(out);
+        }
+        Some(out != word)
+    }
+}
+
+impl<S: Stem> Stem for Overrides<S> {
+    fn stem<'a>(&self, word: &'a str) -> Cow<'a, str> {
+        let mut out = String::new();
+        if self.stem_into(word, &mut out) {
+            Cow::Owned(out)
+        } else {
+            Cow::Borrowed(word)
+        }
+    }
+
+    fn stem_into(&self, word: &str, out: &mut String) -> bool {
+        self.lookup(word, out)
+            .unwrap_or_else(|| self.stemmer.stem_into(word, out))
+    }
+
+    fn stem_into_with(&self, word: &str, out: &mut String, options: &StemmerOptions) -> bool {
+        self.lookup(word, out)
+            .unwrap_or_else(|| self.stemmer.stem_into_with(word, out, options))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::Suffix;
+
+    #[test]
+    fn test_overrides() {
+        let mut stemmer = Overrides::new(Suffix);
+        stemmer.insert("ludzie", "człowiek");
+        stemmer.insert("Ludzie", "Człowiek");
+        stemmer.protect("kotami");
+        assert_eq!(stemmer.stem("ludzie"), "człowiek");
+        assert_eq!(stemmer.stem("Ludzie"), "Człowiek");
+        assert_eq!(stemmer.stem("LUDZIE"), "LUDZIEx");
+        assert!(matches!(stemmer.stem("kotami"), Cow::Borrowed("kotami")));
+        assert_eq!(stemmer.stem("psami"), "psamix");
+
+        stemmer.protect("ludzie");
+        assert_eq!(stemmer.stem("ludzie"), "ludzie");
+        stemmer.insert("kotami", "kot");
+        assert_eq!(stemmer.stem("kotami"), "kot");
+
+        let stemmer = stemmer.ignore_case();
+        assert_eq!(stemmer.stem("LUDZIE"), "LUDZIE");
+        assert_eq!(stemmer.stem("KOTAMI"), "KOT");
+        assert_eq!(stemmer.stem("Kotami"), "Kot");
+        assert_eq!(stemmer.stem("PSAMI"), "PSAMIx");
+    }
+
+    #[test]
+    fn test_ignore_case_collisions() {
+        let mut stemmer = Overrides::new(Suffix);
+        stemmer.insert("Kraków", "a");
+        stemmer.insert("KRAKÓW", "b");
+        stemmer.insert("ludzie", "człowiek");
+        stemmer.protect("LUDZIE");
+        stemmer.protect("Kotami");
+        stemmer.insert("kotami", "kot");
+        // Enough colliding words that the order of a hash map would likely differ from the order they were added in.
+        for i in 0..100 {
+            stemmer.insert(&format!("pies{}", i), "x");
+            stemmer.insert(&format!("PIES{}", i), "y");
+        }
+
+        let stemmer = stemmer.ignore_case();
+        assert_eq!(stemmer.stem("kraków"), "b");
+        assert!(matches!(stemmer.stem("Ludzie"), Cow::Borrowed("Ludzie")));
+        assert_eq!(stemmer.stem("KOTAMI"), "KOT");
+        for i in 0..100 {
+            assert_eq!(stemmer.stem(&format!("pies{}", i)), "y");
+        }
+        assert_eq!(stemmer.overrides.len(), 103);
+    }
+
+    #[test]
+    fn test_load_tsv() {
+        let tsv = "# irregular forms\nludzie\tczłowiek\r\n\nKraków\nlepszy\tdobry\n";
+        let mut stemmer = Overrides::new(Suffix);
+        stemmer.load_tsv(tsv.as_bytes()).unwrap();
+        assert_eq!(stemmer.stem("ludzie"), "człowiek");
+        assert_eq!(stemmer.stem("lepszy"), "dobry");
+        assert_eq!(stemmer.stem("Kraków"), "Kraków");
+        assert_eq!(stemmer.stem("kraków"), "krakówx");
+
+        for (tsv, line) in [("a\tb\nc\td\te\n", 2), ("\tb\n", 1), ("a\t\n", 1)] {
+            let err = Overrides::new(Suffix).load_tsv(tsv.as_bytes()).unwrap_err();
+            assert!(
+                matches!(err, Error::InvalidOverride { line: l } if l == line),
+                "{:?}",
+                tsv
+            );
+        }
+    }
+
+    #[cfg(feature = "table_2000")]
+    #[test]
+    fn test_replaces_stemmer() {
+        use crate::embedded::STEMMER;
+
+        let mut stemmer = Overrides::new(&STEMMER);
+        stemmer.insert("ludzie", "człowiek");
+        for word in ["kotami", "książkami", "czytałem"] {
+            assert_eq!(stemmer.stem(word), STEMMER.stem(word));
+        }
+        assert_eq!(stemmer.stem("ludzie"), "człowiek");
+    }
+}
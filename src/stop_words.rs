@@ -0,0 +1,123 @@
+use crate::Error;
+use std::collections::HashSet;
+use std::io;
+
+/// A set of words which are dropped before stemming by an [`Analyzer`](crate::Analyzer), such as conjunctions and
+/// prepositions which are too common to be useful search terms.
+///
+/// ```rust
+/// use rstempel::StopWords;
+/// let mut stop_words: StopWords = ["i", "w", "na"].into_iter().collect();
+/// stop_words.load("# comment\nz\nze\n".as_bytes())?;
+/// assert!(stop_words.contains("ze"));
+/// assert!(!stop_words.contains("kot"));
+/// # Ok::<(), rstempel::Error>(())
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StopWords {
+    words: HashSet<String>,
+}
+
+impl StopWords {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the Polish stop words shipped with Lucene's `PolishAnalyzer`, in lowercase, so that an [`Analyzer`]
+    /// drops the same words as a Lucene index. To use another list, load it with [`StopWords::load`].
+    ///
+    /// [`Analyzer`]: crate::Analyzer
+    #[cfg(feature = "stop_words")]
+    pub fn polish() -> Self {
+        let mut words = Self::new();
+        words.add_lines(include_str!("tables/stop_words_pl.txt").lines());
+        words
+    }
+
+    /// Adds the words in a file with a word on each line, like the `stopwords.txt` files used by Lucene. Empty lines,
+    /// and lines starting with `#`, are skipped.
+    pub fn load<R: io::BufRead>(&mut self, reader: R) -> Result<(), Error> {
+        for line in reader.lines() {
+            self.add_lines(std::iter::once(line?.as_str()));
+        }
+        Ok(())
+    }
+
+    fn add_lines<'a>(&mut self, lines: impl Iterator<Item = &'a str>) {
+        for line in lines {
+            let word = line.trim();
+            if !word.is_empty() && !word.starts_with('#') {
+                self.insert(word);
+            }
+        }
+    }
+
+    /// Adds `word`, returning `true` if it wasn't in the set already.
+    pub fn insert(&mut self, word: impl Into<String>) -> bool {
+        self.words.insert(word.into())
+    }
+
+    pub fn remove(&mut self, word: &str) -> bool {
+        self.words.remove(word)
+    }
+
+    pub fn contains(&self, word: &str) -> bool {
+        self.words.contains(word)
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    /// Applies `f` to every word, such as to lowercase them.
+    pub(crate) fn map(self, f: impl Fn(&str) -> String) -> Self {
+        self.words.iter().map(|word| f(word)).collect()
+    }
+}
+
+impl<W: Into<String>> FromIterator<W> for StopWords {
+    fn from_iter<I: IntoIterator<Item = W>>(iter: I) -> Self {
+        let mut words = Self::new();
+        words.extend(iter);
+        words
+    }
+}
+
+impl<W: Into<String>> Extend<W> for StopWords {
+    fn extend<I: IntoIterator<Item = W>>(&mut self, iter: I) {
+        self.words.extend(iter.into_iter().map(Into::into));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_load() {
+        let mut stop_words = StopWords::new();
+        stop_words
+            .load("# Polish stop words\n\nsię\r\n  oraz \n#nie\n".as_bytes())
+            .unwrap();
+        assert_eq!(stop_words.len(), 2);
+        assert!(stop_words.contains("się") && stop_words.contains("oraz"));
+        assert!(!stop_words.contains("nie") && !stop_words.contains("#nie"));
+        assert!(stop_words.remove("oraz") && !stop_words.contains("oraz"));
+    }
+
+    #[cfg(feature = "stop_words")]
+    #[test]
+    fn test_polish() {
+        let stop_words = StopWords::polish();
+        for word in ["i", "się", "że", "który", "np", "tzw", "www", "sposób"] {
+            assert!(stop_words.contains(word), "{}", word);
+        }
+        for word in ["kot", "#", "", "carrot2"] {
+            assert!(!stop_words.contains(word), "{}", word);
+        }
+    }
+}
@@ -0,0 +1,71 @@
+//! Helpers shared by the tests of several modules.
+
+use crate::Stem;
+use std::borrow::Cow;
+
+/// The stemming table from the stempel stemmer project, which most tests of the tables use.
+#[cfg(feature = "external")]
+pub(crate) const STEMMER_2000: &str = "src/tables/stemmer_2000.out.gz";
+
+/// Opens a gzip compressed file, such as `STEMMER_2000`, for reading its decompressed contents.
+#[cfg(any(feature = "external", feature = "table_2000"))]
+pub(crate) fn open_gz(path: &str) -> impl std::io::BufRead {
+    use flate2::bufread::GzDecoder;
+    use std::{fs, io};
+
+    let file = fs::File::open(path).unwrap();
+    io::BufReader::new(GzDecoder::new(io::BufReader::new(file)))
+}
+
+/// Loads [`STEMMER_2000`].
+#[cfg(feature = "external")]
+pub(crate) fn load_stemmer() -> crate::external::Stemmer {
+    crate::external::Stemmer::load(open_gz(STEMMER_2000)).unwrap()
+}
+
+/// Returns about 20000 keys with up to 8 chars which have a command in the first trie of the table, so that the
+/// tables reduced from it stem them alike.
+#[cfg(feature = "external")]
+pub(crate) fn stored_words(stemmer: &crate::external::Stemmer) -> Vec<String> {
+    let trie = match stemmer.table() {
+        crate::external::Table::MultiTrie2(trie) => &trie.t.tries[0],
+        crate::external::Table::Trie(trie) => trie,
+    };
+    let mut keys = Vec::new();
+    let mut stack = vec![(trie.root, String::new())];
+    while let Some((row, suffix)) = stack.pop() {
+        for (&ch, cell) in &trie.rows[row as usize].cells {
+            let key = format!("{}{}", ch, suffix);
+            if cell.cmd.is_some() && key.chars().count() >= 4 {
+                keys.push(key.clone());
+            }
+            match cell.refr {
+                Some(refr) if key.chars().count() < 8 && keys.len() < 20_000 => {
+                    stack.push((refr, key))
+                }
+                _ => {}
+            }
+        }
+    }
+    keys
+}
+
+/// Returns a sample of words for the table: the [`stored_words`], on their own and with endings, which make the first
+/// part of their commands skip chars.
+#[cfg(feature = "external")]
+pub(crate) fn sample_words(stemmer: &crate::external::Stemmer) -> Vec<String> {
+    let endings = ["", "e", "ie", "ach"];
+    stored_words(stemmer)
+        .iter()
+        .flat_map(|key| endings.map(|ending| format!("{}{}", key, ending)))
+        .collect()
+}
+
+/// Appends "x" to every word.
+pub(crate) struct Suffix;
+
+impl Stem for Suffix {
+    fn stem<'a>(&self, word: &'a str) -> Cow<'a, str> {
+        Cow::Owned(format!("{}x", word))
+    }
+}
@@ -0,0 +1,584 @@
+//! Splits Polish text into tokens which can be passed to a [`Stem`] implementation.
+//!
+//! Words are runs of letters. An apostrophe between letters is part of the word, as in inflected foreign names like
+//! `Kennedy'ego`, and so is a hyphen between letters or digits, as in `polsko-niemiecki`. Numbers, URLs, e-mail
+//! addresses and punctuation are kept as tokens of their own kind, which shouldn't be stemmed, and whitespace is
+//! skipped.
+//!
+//! ```rust
+//! use rstempel::tokenize::{tokenize, TokenKind};
+//! let words: Vec<&str> = tokenize("Kot (3,5 kg) pisze na kot@example.com.")
+//!     .filter(|token| token.kind == TokenKind::Word)
+//!     .map(|token| token.text)
+//!     .collect();
+//! assert_eq!(words, ["Kot", "kg", "pisze", "na"]);
+//! ```
+
+use crate::Stem;
+use std::borrow::Cow;
+use std::ops::Range;
+
+/// The kind of a [`Token`]. Only [`TokenKind::Word`]s are meant to be stemmed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TokenKind {
+    /// Letters, possibly joined by apostrophes or hyphens, like `kotami`, `O'Connor` or `biało-czerwony`.
+    Word,
+    /// Digits, possibly joined by hyphens or by decimal, thousands or time separators, like `2024`, `3,14`, `1.000.000`
+    /// or `12:30`.
+    Number,
+    /// Letters mixed with digits, like `mp3`, `COVID-19` or `5-ty`.
+    Alphanumeric,
+    /// A URL starting with `http://`, `https://`, `ftp://` or `www.`.
+    Url,
+    Email,
+    /// Any other char which isn't whitespace, like `.`, `„` or `€`, as a token of its own.
+    Punctuation,
+}
+
+/// A token of the text passed to [`tokenize`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Token<'a> {
+    pub text: &'a str,
+    /// The byte offset of the token in the text.
+    pub start: usize,
+    /// The byte offset of the end of the token in the text, so that `text == &input[start..end]`.
+    pub end: usize,
+    pub kind: TokenKind,
+}
+
+impl Token<'_> {
+    /// Returns the byte range of the token in the text.
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+
+    /// True if the token is a [`TokenKind::Word`], which can be stemmed.
+    pub fn is_word(&self) -> bool {
+        self.kind == TokenKind::Word
+    }
+}
+
+/// Returns an iterator over the tokens of `text`, in order.
+pub fn tokenize(text: &str) -> Tokens<'_> {
+    Tokens {
+        text,
+        pos: 0,
+        no_email_until: 0,
+    }
+}
+
+/// An iterator over the tokens of a text, see [`tokenize`].
+#[derive(Debug, Clone)]
+pub struct Tokens<'a> {
+    text: &'a str,
+    pos: usize,
+    /// The offset before which no e-mail address starts, so that the text before an `@` is only scanned once.
+    no_email_until: usize,
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        let rest = &self.text[self.pos..];
+        let trimmed = rest.trim_start();
+        if trimmed.is_empty() {
+            self.pos = self.text.len();
+            return None;
+        }
+        let start = self.pos + (rest.len() - trimmed.len());
+        let (len, kind) = url_len(trimmed)
+            .map(|len| (len, TokenKind::Url))
+            .or_else(|| self.email_len(start, trimmed))
+            .or_else(|| word_len(trimmed))
+            .unwrap_or_else(|| (punctuation_len(trimmed), TokenKind::Punctuation));
+        self.pos = start + len;
+        Some(Token {
+            text: &self.text[start..self.pos],
+            start,
+            end: self.pos,
+            kind,
+        })
+    }
+}
+
+impl std::iter::FusedIterator for Tokens<'_> {}
+
+impl Tokens<'_> {
+    fn email_len(&mut self, start: usize, s: &str) -> Option<(usize, TokenKind)> {
+        if start < self.no_email_until {
+            return None;
+        }
+        match email_len(s) {
+            Ok(len) => Some((len, TokenKind::Email)),
+            Err(len) => {
+                self.no_email_until = start + len;
+                None
+            }
+        }
+    }
+}
+
+/// A token of the text passed to [`Stem::stem_text`], with its stem.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct StemmedToken<'a> {
+    /// The token as it appears in the text.
+    pub text: &'a str,
+    /// The byte offset of the token in the text.
+    pub start: usize,
+    /// The byte offset of the end of the token in the text.
+    pub end: usize,
+    /// The index of the token among the tokens returned for the text, starting at 0.
+    pub position: usize,
+    pub kind: TokenKind,
+    /// The stem of a [`TokenKind::Word`], borrowed from the text if it's unchanged. Tokens of other kinds are their own
+    /// stems.
+    pub stem: Cow<'a, str>,
+}
+
+impl StemmedToken<'_> {
+    /// Returns the byte range of the token in the text.
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+}
+
+/// An iterator over the stemmed tokens of a text, see [`Stem::stem_text`].
+#[derive(Debug, Clone)]
+pub struct StemmedTokens<'s, 't, S> {
+    stemmer: &'s S,
+    tokens: Tokens<'t>,
+    position: usize,
+}
+
+impl<'s, 't, S: Stem> StemmedTokens<'s, 't, S> {
+    pub(crate) fn new(stemmer: &'s S, text: &'t str) -> Self {
+        Self {
+            stemmer,
+            tokens: tokenize(text),
+            position: 0,
+        }
+    }
+}
+
+impl<'t, S: Stem> Iterator for StemmedTokens<'_, 't, S> {
+    type Item = StemmedToken<'t>;
+
+    fn next(&mut self) -> Option<StemmedToken<'t>> {
+        let token = self
+            .tokens
+            .find(|token| token.kind != TokenKind::Punctuation)?;
+        let stem = if token.is_word() {
+            self.stemmer.stem(token.text)
+        } else {
+            Cow::Borrowed(token.text)
+        };
+        let position = self.position;
+        self.position += 1;
+        Some(StemmedToken {
+            text: token.text,
+            start: token.start,
+            end: token.end,
+            position,
+            kind: token.kind,
+            stem,
+        })
+    }
+}
+
+impl<S: Stem> std::iter::FusedIterator for StemmedTokens<'_, '_, S> {}
+
+/// Combining marks which follow a letter are part of it, so that text which isn't in NFC is split like text which is.
+/// Covers the blocks of combining diacritical marks, which is every mark used with Latin letters.
+fn is_combining_mark(ch: char) -> bool {
+    matches!(
+        ch,
+        '\u{300}'..='\u{36F}'
+            | '\u{1AB0}'..='\u{1AFF}'
+            | '\u{1DC0}'..='\u{1DFF}'
+            | '\u{20D0}'..='\u{20FF}'
+            | '\u{FE20}'..='\u{FE2F}'
+    )
+}
+
+fn is_apostrophe(ch: char) -> bool {
+    matches!(ch, '\'' | '\u{2019}')
+}
+
+pub(crate) fn is_hyphen(ch: char) -> bool {
+    matches!(ch, '-' | '\u{2010}')
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Class {
+    Letter,
+    Digit,
+}
+
+fn class(ch: char) -> Option<Class> {
+    if ch.is_alphabetic() {
+        Some(Class::Letter)
+    } else if ch.is_numeric() {
+        Some(Class::Digit)
+    } else {
+        None
+    }
+}
+
+/// Returns the length and kind of the word, number or alphanumeric token at the start of `s`, if it starts with one.
+fn word_len(s: &str) -> Option<(usize, TokenKind)> {
+    let mut chars = s.char_indices().peekable();
+    let (mut letters, mut digits) = (false, false);
+    let mut prev = None;
+    let mut len = 0;
+    while let Some((idx, ch)) = chars.next() {
+        let current = match (class(ch), prev) {
+            (Some(current), _) => current,
+            (None, Some(prev)) if is_combining_mark(ch) => prev,
+            (None, Some(prev)) => {
+                // A joiner is only part of the token if it's followed by a char it can join.
+                let next = chars.peek().and_then(|&(_, next)| class(next));
+                let joins = match (prev, next) {
+                    (_, None) => false,
+                    (Class::Letter, Some(Class::Letter)) if is_apostrophe(ch) => true,
+                    (_, Some(_)) if is_hyphen(ch) => true,
+                    (Class::Digit, Some(Class::Digit)) => matches!(ch, '.' | ',' | ':'),
+                    _ => false,
+                };
+                if !joins {
+                    break;
+                }
+                prev
+            }
+            (None, None) => break,
+        };
+        match current {
+            Class::Letter => letters = true,
+            Class::Digit => digits = true,
+        }
+        prev = Some(current);
+        len = idx + ch.len_utf8();
+    }
+    let kind = match (letters, digits) {
+        (false, false) => return None,
+        (true, false) => TokenKind::Word,
+        (false, true) => TokenKind::Number,
+        (true, true) => TokenKind::Alphanumeric,
+    };
+    Some((len, kind))
+}
+
+/// Returns the length of the URL at the start of `s`, if it starts with one. Trailing punctuation, such as the full
+/// stop after a URL at the end of a sentence, isn't part of the URL.
+fn url_len(s: &str) -> Option<usize> {
+    let prefix = ["http://", "https://", "ftp://", "www."]
+        .iter()
+        .find(|prefix| {
+            s.get(..prefix.len())
+                .is_some_and(|start| start.eq_ignore_ascii_case(prefix))
+        })?;
+    // Checked before scanning the rest of the URL, so that a prefix without a host isn't scanned again at every token
+    // after it. The host's first char is never trimmed below.
+    if !s[prefix.len()..].starts_with(char::is_alphanumeric) {
+        return None;
+    }
+    // The number of closing brackets without an opening one.
+    let (mut parens, mut brackets) = (0isize, 0isize);
+    let mut end = s.len();
+    for (idx, ch) in s.char_indices() {
+        match ch {
+            '(' => parens -= 1,
+            ')' => parens += 1,
+            '[' => brackets -= 1,
+            ']' => brackets += 1,
+            _ if ch.is_whitespace() || matches!(ch, '<' | '>' | '"' | '„' | '”' | '«' | '»') =>
+            {
+                end = idx;
+                break;
+            }
+            _ => {}
+        }
+    }
+    let mut url = &s[..end];
+    while let Some(last) = url.chars().next_back() {
+        let trailing = match last {
+            ')' => parens > 0,
+            ']' => brackets > 0,
+            _ => matches!(last, '.' | ',' | ';' | ':' | '!' | '?') || is_apostrophe(last),
+        };
+        if !trailing {
+            break;
+        }
+        match last {
+            ')' => parens -= 1,
+            ']' => brackets -= 1,
+            _ => {}
+        }
+        url = &url[..url.len() - last.len_utf8()];
+    }
+    Some(url.len())
+}
+
+/// Returns the length of the e-mail address at the start of `s`, if it starts with one. The domain must have at least
+/// two labels, and end in a label of letters.
+///
+/// Otherwise returns the length of the start of `s` in which no address can start either, since any address starting
+/// there would have the same `@` and domain.
+fn email_len(s: &str) -> Result<usize, usize> {
+    let is_local = |ch: char| ch.is_alphanumeric() || matches!(ch, '.' | '_' | '%' | '+' | '-');
+    let at = s.find(|ch: char| !is_local(ch)).ok_or(s.len())?;
+    if !s[at..].starts_with('@') {
+        return Err(at);
+    }
+    // An address can still start after a leading dot.
+    if at == 0 || s.starts_with('.') {
+        return Err(0);
+    }
+    let domain = &s[at + 1..];
+    let mut rest = domain;
+    let (mut len, mut labels, mut letters) = (0, 0, false);
+    loop {
+        let label_len = rest
+            .find(|ch: char| !(ch.is_alphanumeric() || ch == '-'))
+            .unwrap_or(rest.len());
+        let label = &rest[..label_len];
+        if label.is_empty() || label.starts_with('-') || label.ends_with('-') {
+            break;
+        }
+        labels += 1;
+        len = domain.len() - rest.len() + label_len;
+        letters = label.chars().nth(1).is_some() && label.chars().all(char::is_alphabetic);
+        match rest[label_len..].strip_prefix('.') {
+            Some(next) => rest = next,
+            None => break,
+        }
+    }
+    if labels >= 2 && letters {
+        Ok(at + 1 + len)
+    } else {
+        Err(at)
+    }
+}
+
+/// Returns the length of the punctuation char at the start of `s`, with any combining marks after it.
+fn punctuation_len(s: &str) -> usize {
+    let mut chars = s.char_indices();
+    chars.next();
+    chars
+        .find(|&(_, ch)| !is_combining_mark(ch))
+        .map_or(s.len(), |(idx, _)| idx)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use TokenKind::*;
+
+    fn tokens(text: &str) -> Vec<(&str, TokenKind)> {
+        tokenize(text)
+            .map(|token| {
+                assert_eq!(&text[token.range()], token.text);
+                (token.text, token.kind)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_words() {
+        let text = " Zażółć gęślą\tjaźń.\n";
+        let all: Vec<_> = tokenize(text).collect();
+        assert_eq!(all.len(), 4);
+        assert_eq!(
+            all[1],
+            Token {
+                text: "gęślą",
+                start: 12,
+                end: 20,
+                kind: Word,
+            }
+        );
+        assert_eq!(all[3].range(), 27..28);
+        assert!(all[0].is_word() && !all[3].is_word());
+        assert!(tokenize(" \n ").next().is_none());
+        // Combining marks are part of the word they follow, and punctuation keeps its own.
+        assert_eq!(
+            tokens("ksia\u{328}z\u{307}ka ,\u{301}"),
+            [("ksia\u{328}z\u{307}ka", Word), (",\u{301}", Punctuation)]
+        );
+    }
+
+    #[test]
+    fn test_apostrophes_and_hyphens() {
+        assert_eq!(
+            tokens("Kennedy'ego i O’Connora, 'kot' - biało-czerwony polsko- i COVID-19"),
+            [
+                ("Kennedy'ego", Word),
+                ("i", Word),
+                ("O’Connora", Word),
+                (",", Punctuation),
+                ("'", Punctuation),
+                ("kot", Word),
+                ("'", Punctuation),
+                ("-", Punctuation),
+                ("biało-czerwony", Word),
+                ("polsko", Word),
+                ("-", Punctuation),
+                ("i", Word),
+                ("COVID-19", Alphanumeric),
+            ]
+        );
+        assert_eq!(tokens("rock'n'roll"), [("rock'n'roll", Word)]);
+        assert_eq!(tokens("5'"), [("5", Number), ("'", Punctuation)]);
+    }
+
+    #[test]
+    fn test_numbers() {
+        assert_eq!(
+            tokens("W 2024 r. o 12:30 było 3,14 i 1.000.000, 5-ty raz w mp3... 1990-2000."),
+            [
+                ("W", Word),
+                ("2024", Number),
+                ("r", Word),
+                (".", Punctuation),
+                ("o", Word),
+                ("12:30", Number),
+                ("było", Word),
+                ("3,14", Number),
+                ("i", Word),
+                ("1.000.000", Number),
+                (",", Punctuation),
+                ("5-ty", Alphanumeric),
+                ("raz", Word),
+                ("w", Word),
+                ("mp3", Alphanumeric),
+                (".", Punctuation),
+                (".", Punctuation),
+                (".", Punctuation),
+                ("1990-2000", Number),
+                (".", Punctuation),
+            ]
+        );
+        assert_eq!(
+            tokens("1, 2"),
+            [("1", Number), (",", Punctuation), ("2", Number)]
+        );
+    }
+
+    #[test]
+    fn test_urls() {
+        assert_eq!(
+            tokens("Zobacz https://pl.wikipedia.org/wiki/Kot_(ssak), (www.example.pl) i http://."),
+            [
+                ("Zobacz", Word),
+                ("https://pl.wikipedia.org/wiki/Kot_(ssak)", Url),
+                (",", Punctuation),
+                ("(", Punctuation),
+                ("www.example.pl", Url),
+                (")", Punctuation),
+                ("i", Word),
+                ("http", Word),
+                (":", Punctuation),
+                ("/", Punctuation),
+                ("/", Punctuation),
+                (".", Punctuation),
+            ]
+        );
+        assert_eq!(
+            tokens("„HTTP://EXAMPLE.COM/a?b=c”"),
+            [
+                ("„", Punctuation),
+                ("HTTP://EXAMPLE.COM/a?b=c", Url),
+                ("”", Punctuation),
+            ]
+        );
+        assert_eq!(tokens("wwwa"), [("wwwa", Word)]);
+        assert_eq!(tokens("www."), [("www", Word), (".", Punctuation)]);
+    }
+
+    #[test]
+    fn test_emails() {
+        assert_eq!(
+            tokens("Napisz do jan.kowalski+kot@poczta.example.pl. albo a@b, x@y.z lub @kot."),
+            [
+                ("Napisz", Word),
+                ("do", Word),
+                ("jan.kowalski+kot@poczta.example.pl", Email),
+                (".", Punctuation),
+                ("albo", Word),
+                ("a", Word),
+                ("@", Punctuation),
+                ("b", Word),
+                (",", Punctuation),
+                ("x", Word),
+                ("@", Punctuation),
+                ("y", Word),
+                (".", Punctuation),
+                ("z", Word),
+                ("lub", Word),
+                ("@", Punctuation),
+                ("kot", Word),
+                (".", Punctuation),
+            ]
+        );
+        assert_eq!(tokens("józef@żółw.pl"), [("józef@żółw.pl", Email)]);
+        assert_eq!(
+            tokens("kot@-a.pl"),
+            [
+                ("kot", Word),
+                ("@", Punctuation),
+                ("-", Punctuation),
+                ("a", Word),
+                (".", Punctuation),
+                ("pl", Word)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_covers_text() {
+        // Every char which isn't whitespace is in exactly one token.
+        for text in [
+            "\u{301}a\u{301}",
+            "-'-' ''",
+            "a@",
+            "@.pl http:// www.a",
+            "1,,2::3--4",
+            "x@y.pl-",
+            "kot\u{2019}",
+        ] {
+            let mut pos = 0;
+            for token in tokenize(text) {
+                assert!(text[pos..token.start].trim().is_empty(), "{:?}", text);
+                assert!(token.start < token.end);
+                pos = token.end;
+            }
+            assert!(text[pos..].trim().is_empty(), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn test_long_runs() {
+        // These would take quadratic time if each token rescanned the rest of the run.
+        let text = "a.".repeat(100_000);
+        assert_eq!(tokenize(&text).count(), 200_000);
+        let text = format!("{}@", "a.".repeat(100_000));
+        assert_eq!(tokenize(&text).count(), 200_001);
+        let text = "www..".repeat(50_000);
+        assert_eq!(tokenize(&text).count(), 150_000);
+        let text = format!("www.a{}", ")".repeat(100_000));
+        assert_eq!(tokenize(&text).count(), 100_001);
+    }
+
+    #[cfg(feature = "table_2000")]
+    #[test]
+    fn test_stem_tokens() {
+        use crate::embedded::STEMMER;
+        use crate::Stem;
+
+        let stems: Vec<_> = tokenize("Kotami, 2024!")
+            .filter(Token::is_word)
+            .map(|token| STEMMER.stem(token.text))
+            .collect();
+        assert_eq!(stems, [STEMMER.stem("Kotami")]);
+    }
+}
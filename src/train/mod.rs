@@ -0,0 +1,167 @@
+//! Compiles stemming tables from inflected words and their stems, like the Java stempel/Egothor `Compile` tool.
+//!
+//! ```rust
+//! use rstempel::Stem;
+//! let mut trainer = rstempel::train::Trainer::new("-M");
+//! trainer.add("kotami", "kot");
+//! trainer.add("kotem", "kot");
+//! let stemmer = trainer.build();
+//! assert_eq!(stemmer.stem("kotami"), "kot");
+//! ```
+
+use crate::external::diff;
+use crate::external::multitrie::MultiTrie2;
+use crate::external::reduce::Reduce;
+use crate::external::trie::Trie;
+use crate::external::{Stemmer, Table};
+use crate::Command;
+use std::io;
+
+/// Builds a stemming table from word/stem pairs.
+///
+/// As with the Java tool, a [`MultiTrie2`] table is not guaranteed to reproduce every training pair: words whose
+/// commands are split across tries can collide on the shortened keys used by later tries.
+pub struct Trainer {
+    method: String,
+    store_original: bool,
+    reductions: Vec<Reduce>,
+    table: Table,
+}
+
+impl Trainer {
+    /// Creates a trainer for a table described by a Java `Compile` method string, e.g. `-ME2`.
+    ///
+    /// A leading `-` reads words from the end instead of the start. A following `0` also stores every stem as
+    /// stemming to itself, and then an `M` builds a [`MultiTrie2`] instead of a single [`Trie`]. Each remaining char
+    /// selects a [`Reduce`] pass which [`Trainer::build`] applies in order, others are ignored. The method string is
+    /// stored with the table.
+    pub fn new(method: &str) -> Self {
+        let mut flags = method.chars().peekable();
+        let forward = flags.next_if_eq(&'-').is_none();
+        let store_original = flags.next_if_eq(&'0').is_some();
+        let multi = flags.next_if_eq(&'M').is_some();
+        let reductions = flags.filter_map(Reduce::from_method_char).collect();
+        let table = if multi {
+            Table::MultiTrie2(MultiTrie2::new(forward))
+        } else {
+            Table::Trie(Trie::new(forward))
+        };
+        Self {
+            method: method.into(),
+            store_original,
+            reductions,
+            table,
+        }
+    }
+
+    /// Adds a single inflected word and its stem.
+    pub fn add(&mut self, word: &str, stem: &str) {
+        if self.store_original {
+            self.table.add(stem, &[Command::Skip { chars: 0 }]);
+        }
+        if word != stem {
+            self.table.add(word, &diff::compute_commands(word, stem));
+        }
+    }
+
+    /// Adds a stem and all of its inflected forms.
+    pub fn add_stem<'a>(&mut self, stem: &str, words: impl IntoIterator<Item = &'a str>) {
+        if self.store_original {
+            self.table.add(stem, &[Command::Skip { chars: 0 }]);
+        }
+        for word in words {
+            if word != stem {
+                self.table.add(word, &diff::compute_commands(word, stem));
+            }
+        }
+    }
+
+    /// Adds every line of a dictionary in the format used by the Java `Compile` tool: a stem followed by its
+    /// inflected forms, separated by whitespace. Lines are lowercased before they are added.
+    pub fn read_dictionary(&mut self, reader: impl io::BufRead) -> io::Result<()> {
+        for line in reader.lines() {
+            let line = line?.to_lowercase();
+            let mut words = line.split_whitespace();
+            if let Some(stem) = words.next() {
+                self.add_stem(stem, words);
+            }
+        }
+        Ok(())
+    }
+
+    /// Finishes training, applies the optimization passes and returns the compiled stemmer.
+    pub fn build(self) -> Stemmer {
+        let table = self.reductions.into_iter().fold(self.table, Table::reduce);
+        Stemmer::new(self.method, table)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Stem;
+
+    const DICTIONARY: &str = "\
+kot kota kotu kotem kocie koty kotów kotom kotami kotach
+książka książki książce książkę książką książek książkom książkami książkach
+czytać czytam czytasz czyta czytamy czytacie czytają czytałem czytała
+";
+
+    #[test]
+    fn test_stems_training_words() {
+        for method in ["-M", "M", "-", "", "-ME2", "-M1", "-ML"] {
+            let mut trainer = Trainer::new(method);
+            trainer.read_dictionary(DICTIONARY.as_bytes()).unwrap();
+            let stemmer = trainer.build();
+            assert_eq!(stemmer.method(), method);
+            for line in DICTIONARY.lines() {
+                let mut words = line.split_whitespace();
+                let stem = words.next().unwrap();
+                for word in words.filter(|w| w.chars().count() > 3) {
+                    assert_eq!(stemmer.stem(word), stem, "method={} word={}", method, word);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let mut trainer = Trainer::new("-ME2");
+        trainer.read_dictionary(DICTIONARY.as_bytes()).unwrap();
+        let mut saved = Vec::new();
+        trainer.build().save(&mut saved).unwrap();
+        let stemmer = Stemmer::load(saved.as_slice()).unwrap();
+        assert_eq!(stemmer.method(), "-ME2");
+        assert_eq!(stemmer.stem("książkami"), "książka");
+    }
+
+    #[test]
+    fn test_long_runs() {
+        // Runs longer than a single command can hold are split across several commands.
+        let long = "a".repeat(300);
+        let (word, stem) = (format!("b{}", long), format!("c{}", long));
+        for method in ["-M", "-", "-ME2"] {
+            let mut trainer = Trainer::new(method);
+            trainer.add(&word, &stem);
+            trainer.add_stem("kot", [format!("kot{}", "x".repeat(600)).as_str()]);
+            let stemmer = trainer.build();
+            assert_eq!(stemmer.stem(&word), stem, "method={}", method);
+            assert_eq!(
+                stemmer.stem(&format!("kot{}", "x".repeat(600))),
+                "kot",
+                "method={}",
+                method
+            );
+        }
+    }
+
+    #[test]
+    fn test_store_original() {
+        let mut trainer = Trainer::new("-0M");
+        trainer.add("kotami", "kot");
+        trainer.add("psami", "pies");
+        let stemmer = trainer.build();
+        assert_eq!(stemmer.stem("kotami"), "kot");
+        assert_eq!(stemmer.stem("pies"), "pies");
+    }
+}